@@ -2,19 +2,34 @@ drop table player;
 create table player (
 	player_id serial primary key,
 	username text unique,
+	--Argon2id PHC string, rows still holding a plaintext password are hashed on the next login
 	password text,
+	--Made by guest login with a generated name, left out of the scoreboard until upgraded
+	guest boolean not null default false,
 	created_on timestamp default now()
 );
 
+drop table move;
 drop table session;
 create table session (
 	session_id serial primary key,
 	player1_username text,
 	player2_username text,
 	result text,
-	board varchar(1)[3][3],
+	game text default 'XO',
+	board text,
+	bot text,
 	created_on timestamp default now()
 );
 
+create table move (
+	session_id integer references session(session_id) on delete cascade,
+	ply integer,
+	player integer,
+	player_input integer,
+	played_at timestamp,
+	primary key (session_id, ply)
+);
+
 insert into player(username, password) values ('kto', 'kto');
 insert into player(username, password) values ('kto1', 'kto1');
\ No newline at end of file
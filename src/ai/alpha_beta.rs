@@ -0,0 +1,97 @@
+use crate::game::{Game, MoveOutcome};
+
+//Score of a won position, faster wins score higher
+pub const WIN: i32 = 1_000_000;
+
+//*
+// Negamax search with alpha-beta pruning. Scores are from the point of view of the player to move:
+// positive is winning, negative is losing and 0 is a draw or unknown when the search is cut at depth.
+// Every legal move is searched with the full window so moves with the same score can be told apart.
+// depth None search until the end of the game
+// */
+pub fn score_moves(game: &(impl Game + Clone), depth: Option<usize>) -> Vec<(usize, i32)> {
+    let depth = depth.unwrap_or(usize::MAX);
+    game.legal_moves()
+        .into_iter()
+        .filter_map(|player_input| {
+            score_move(game, player_input, depth, 0, -WIN * 2, WIN * 2).map(|score| (player_input, score))
+        })
+        .collect()
+}
+
+//Score of playing player_input, None if the game doesn't accept it
+pub fn score_move(game: &(impl Game + Clone), player_input: usize, depth: usize, ply: i32, alpha: i32, beta: i32) -> Option<i32> {
+    let mut child = game.clone();
+    match child.make_a_move(player_input) {
+        Ok(MoveOutcome::Win(_)) => Some(WIN - ply),
+        Ok(MoveOutcome::Draw) => Some(0),
+        Ok(MoveOutcome::Continue) => Some(-negamax(&child, depth - 1, ply + 1, -beta, -alpha)),
+        Err(_) => None
+    }
+}
+
+fn negamax(game: &(impl Game + Clone), depth: usize, ply: i32, mut alpha: i32, beta: i32) -> i32 {
+    if depth == 0 {
+        return 0;
+    }
+
+    let mut best = -WIN * 2;
+    for player_input in game.legal_moves() {
+        if let Some(score) = score_move(game, player_input, depth, ply, alpha, beta) {
+            best = best.max(score);
+            alpha = alpha.max(score);
+            if alpha >= beta {
+                break;
+            }
+        }
+    }
+    best
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::game::Game;
+    use crate::game::testing::{never_loses, play};
+    use crate::game::xo::XO;
+    use super::{score_moves, WIN};
+
+    //Every move with the best score
+    fn best_moves(game: &XO) -> Vec<usize> {
+        let scores = score_moves(game, None);
+        let best = scores.iter().map(|(_, score)| *score).max().unwrap();
+        scores.into_iter().filter(|(_, score)| *score == best).map(|(input, _)| input).collect()
+    }
+
+    #[test]
+    fn never_loses_classic_xo_moving_first() {
+        assert!(never_loses(&XO::new(), true, &best_moves));
+    }
+
+    #[test]
+    fn never_loses_classic_xo_moving_second() {
+        assert!(never_loses(&XO::new(), false, &best_moves));
+    }
+
+    #[test]
+    fn empty_board_is_a_draw() {
+        assert!(score_moves(&XO::new(), None).iter().all(|(_, score)| *score == 0));
+    }
+
+    #[test]
+    fn faster_wins_score_higher() {
+        let mut game = XO::new();
+        //X on 1 and 2, O on 4 and 5: X wins now with 3, O would win with 6
+        play(&mut game, &[1, 4, 2, 5]);
+        let scores = score_moves(&game, None);
+        let best = scores.iter().max_by_key(|(_, score)| *score).unwrap();
+        assert_eq!(*best, (3, WIN));
+        assert!(scores.iter().filter(|(input, _)| *input != 3).all(|(_, score)| *score < WIN));
+    }
+
+    #[test]
+    fn search_cut_at_depth_scores_unknown_as_0() {
+        let mut game = XO::new();
+        game.make_a_move(5).unwrap();
+        assert!(score_moves(&game, Some(1)).iter().all(|(_, score)| *score == 0));
+    }
+}
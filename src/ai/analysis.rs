@@ -0,0 +1,102 @@
+use serde::Serialize;
+use crate::ai::alpha_beta::{self, WIN};
+use crate::ai::Bot;
+use crate::game::AnyGame;
+
+//*
+// What happens to the player to move if both players play the best moves from here.
+// Unknown when the game is too big to be searched until the end
+// */
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Prediction {
+    Win,
+    Loss,
+    Draw,
+    Unknown,
+}
+
+impl Prediction {
+    fn from_score(score: i32, complete: bool) -> Self {
+        if score > WIN / 2 {
+            Prediction::Win
+        } else if score < -WIN / 2 {
+            Prediction::Loss
+        } else if complete {
+            Prediction::Draw
+        } else {
+            Prediction::Unknown
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct MoveScore {
+    pub player_input: usize,
+    pub score: i32,
+    pub prediction: Prediction,
+}
+
+#[derive(Serialize)]
+pub struct Analysis {
+    pub prediction: Prediction,
+    pub best_moves: Vec<usize>,
+    pub scores: Vec<MoveScore>,
+    //None when the search went until the end of the game
+    pub depth: Option<usize>,
+}
+
+//*
+// Score every legal move with the same search as the perfect bot
+// */
+pub fn analyze(game: &AnyGame) -> Analysis {
+    let depth = Bot::search_depth(game);
+    let complete = depth.is_none();
+    let scores = alpha_beta::score_moves(game, depth);
+    let best = scores.iter().map(|(_, score)| *score).max();
+
+    Analysis {
+        prediction: best.map_or(Prediction::Unknown, |best| Prediction::from_score(best, complete)),
+        best_moves: scores.iter()
+            .filter(|(_, score)| Some(*score) == best)
+            .map(|(player_input, _)| *player_input)
+            .collect(),
+        scores: scores.iter()
+            .map(|(player_input, score)| MoveScore {
+                player_input: *player_input,
+                score: *score,
+                prediction: Prediction::from_score(*score, complete),
+            })
+            .collect(),
+        depth,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::game::AnyGame;
+    use crate::game::testing::play;
+    use crate::game::xo::XO;
+    use super::{analyze, Prediction};
+
+    #[test]
+    fn won_in_one_has_a_single_best_move() {
+        let mut game = AnyGame::XO(XO::new());
+        //X on 1 and 2, O on 4 and 5: 3 wins right away
+        play(&mut game, &[1, 4, 2, 5]);
+        let analysis = analyze(&game);
+        assert_eq!(analysis.prediction, Prediction::Win);
+        assert_eq!(analysis.best_moves, vec![3]);
+        assert_eq!(analysis.depth, None);
+        let best = analysis.scores.iter().find(|score| score.player_input == 3).unwrap().score;
+        assert!(analysis.scores.iter().filter(|score| score.player_input != 3).all(|score| score.score < best));
+    }
+
+    #[test]
+    fn empty_classic_board_is_a_draw() {
+        let analysis = analyze(&AnyGame::XO(XO::new()));
+        assert_eq!(analysis.prediction, Prediction::Draw);
+        assert_eq!(analysis.best_moves.len(), 9);
+        assert!(analysis.scores.iter().all(|score| score.prediction == Prediction::Draw));
+    }
+}
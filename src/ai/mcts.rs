@@ -0,0 +1,229 @@
+use std::time::{Duration, Instant};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use crate::game::{Game, MoveOutcome};
+
+//*
+// Budget of a Monte Carlo Tree Search. The search stops after `iterations` playouts or when
+// `time_budget` runs out, whichever comes first. With a seed and no time budget the search always
+// returns the same move for the same position
+// */
+#[derive(Clone, Debug)]
+pub struct MctsConfig {
+    pub iterations: usize,
+    pub time_budget: Option<Duration>,
+    pub seed: Option<u64>,
+    //UCT exploration constant, higher values try more moves instead of digging into the best one
+    pub exploration: f64,
+}
+
+impl MctsConfig {
+    pub fn new(iterations: usize, time_budget: Option<Duration>) -> Self {
+        MctsConfig {
+            iterations,
+            time_budget,
+            seed: None,
+            exploration: std::f64::consts::SQRT_2,
+        }
+    }
+}
+
+struct Node {
+    player_input: Option<usize>,
+    parent: Option<usize>,
+    children: Vec<usize>,
+    //moves that don't have a child node yet, shuffled so they are expanded in random order
+    untried: Vec<usize>,
+    //number of moves from the root, the player who moved into this node is (ply - 1) % 2
+    ply: usize,
+    visits: u32,
+    //sum of rewards for the player who moved into this node, 1 for a win and 0.5 for a draw
+    reward: f64,
+    outcome: MoveOutcome,
+}
+
+//*
+// Generic over any game that can list its legal moves and be cloned. Players are counted from the
+// player to move at the root: 0 is the player to move, 1 is the opponent
+// */
+pub fn best_move(game: &(impl Game + Clone), config: &MctsConfig) -> Option<usize> {
+    let mut rng = match config.seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    };
+    let start = Instant::now();
+
+    let mut untried = game.legal_moves();
+    untried.shuffle(&mut rng);
+    let mut tree = vec![Node {
+        player_input: None,
+        parent: None,
+        children: Vec::new(),
+        untried,
+        ply: 0,
+        visits: 0,
+        reward: 0.0,
+        outcome: MoveOutcome::Continue,
+    }];
+
+    for _ in 0..config.iterations {
+        if config.time_budget.is_some_and(|budget| start.elapsed() >= budget) {
+            break;
+        }
+
+        //selection: go down fully expanded nodes
+        let mut state = game.clone();
+        let mut node = 0;
+        while tree[node].untried.is_empty() && !tree[node].children.is_empty() {
+            node = select_child(&tree, node, config.exploration);
+            state.make_a_move(tree[node].player_input.unwrap()).ok();
+        }
+
+        //expansion: add one child for a move that wasn't tried yet
+        if let Some(player_input) = tree[node].untried.pop() {
+            let outcome = match state.make_a_move(player_input) {
+                Ok(outcome) => outcome,
+                Err(_) => continue
+            };
+            let mut untried = state.legal_moves();
+            untried.shuffle(&mut rng);
+            tree.push(Node {
+                player_input: Some(player_input),
+                parent: Some(node),
+                children: Vec::new(),
+                untried,
+                ply: tree[node].ply + 1,
+                visits: 0,
+                reward: 0.0,
+                outcome,
+            });
+            let child = tree.len() - 1;
+            tree[node].children.push(child);
+            node = child;
+        }
+
+        //simulation: play random moves until the game is over
+        let (outcome, ply) = match tree[node].outcome {
+            MoveOutcome::Continue => playout(state, tree[node].ply, &mut rng),
+            outcome => (outcome, tree[node].ply),
+        };
+        let winner = match outcome {
+            MoveOutcome::Win(_) => Some((ply + 1) % 2),
+            _ => None,
+        };
+
+        //backpropagation
+        let mut current = Some(node);
+        while let Some(index) = current {
+            let node = &mut tree[index];
+            node.visits += 1;
+            node.reward += match winner {
+                Some(winner) if winner == (node.ply + 1) % 2 => 1.0,
+                Some(_) => 0.0,
+                None => 0.5,
+            };
+            current = node.parent;
+        }
+    }
+
+    tree[0].children.iter()
+        .max_by_key(|child| tree[**child].visits)
+        .and_then(|child| tree[*child].player_input)
+}
+
+fn select_child(tree: &[Node], node: usize, exploration: f64) -> usize {
+    let parent_visits = (tree[node].visits.max(1) as f64).ln();
+    *tree[node].children.iter()
+        .max_by(|a, b| {
+            uct(&tree[**a], parent_visits, exploration).total_cmp(&uct(&tree[**b], parent_visits, exploration))
+        })
+        .unwrap()
+}
+
+fn uct(node: &Node, parent_visits: f64, exploration: f64) -> f64 {
+    if node.visits == 0 {
+        return f64::INFINITY;
+    }
+    let visits = node.visits as f64;
+    node.reward / visits + exploration * (parent_visits / visits).sqrt()
+}
+
+//Play random moves from ply until the game is over, return the outcome and the ply of the last move
+fn playout(mut state: impl Game, mut ply: usize, rng: &mut StdRng) -> (MoveOutcome, usize) {
+    loop {
+        let player_input = match state.legal_moves().choose(rng) {
+            Some(player_input) => *player_input,
+            None => return (MoveOutcome::Draw, ply)
+        };
+        ply += 1;
+        match state.make_a_move(player_input) {
+            Ok(MoveOutcome::Continue) => {}
+            Ok(outcome) => return (outcome, ply),
+            Err(_) => return (MoveOutcome::Draw, ply)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use crate::game::Game;
+    use crate::game::connect_four::ConnectFour;
+    use crate::game::xo::XO;
+    use super::{best_move, MctsConfig};
+
+    fn seeded(iterations: usize, seed: u64) -> MctsConfig {
+        MctsConfig {
+            seed: Some(seed),
+            ..MctsConfig::new(iterations, None)
+        }
+    }
+
+    #[test]
+    fn same_seed_same_move() {
+        let mut game = XO::with_size(7, 7, 4);
+        for input in [25, 17, 26] {
+            game.make_a_move(input).unwrap();
+        }
+        for seed in 0..3 {
+            let config = seeded(300, seed);
+            assert_eq!(best_move(&game, &config), best_move(&game, &config));
+        }
+    }
+
+    #[test]
+    fn takes_the_winning_move() {
+        let mut game = ConnectFour::new();
+        //X has 3 stacked in column 4
+        for input in [4, 1, 4, 1, 4, 2] {
+            game.make_a_move(input).unwrap();
+        }
+        assert_eq!(best_move(&game, &seeded(2_000, 7)), Some(4));
+    }
+
+    #[test]
+    fn blocks_the_opponent() {
+        let mut game = ConnectFour::new();
+        //O has to stop X stacking a 4th mark in column 4
+        for input in [4, 1, 4, 2, 4] {
+            game.make_a_move(input).unwrap();
+        }
+        assert_eq!(best_move(&game, &seeded(2_000, 7)), Some(4));
+    }
+
+    #[test]
+    fn stops_when_the_time_budget_runs_out() {
+        let config = MctsConfig::new(usize::MAX, Some(Duration::from_millis(50)));
+        assert!(best_move(&XO::gomoku(), &config).is_some());
+    }
+
+    #[test]
+    fn no_move_when_the_game_is_over() {
+        let mut game = XO::new();
+        for input in [1, 4, 2, 5, 3] {
+            game.make_a_move(input).unwrap();
+        }
+        assert_eq!(best_move(&game, &seeded(100, 0)), None);
+    }
+}
@@ -0,0 +1,221 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use std::time::Duration;
+use rand::seq::SliceRandom;
+use serde::{Deserialize, Serialize};
+use crate::ai::mcts::MctsConfig;
+use crate::game::{AnyGame, Game};
+use crate::model::player::Player;
+
+pub mod alpha_beta;
+pub mod analysis;
+pub mod mcts;
+
+//*
+// How hard the computer plays:
+// random picks any legal move, greedy only looks at winning now or blocking the opponent next move,
+// shallow looks a few moves ahead and perfect searches as deep as the game allows
+// */
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Difficulty {
+    Random,
+    Greedy,
+    Shallow,
+    Perfect,
+}
+
+impl Display for Difficulty {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Difficulty::Random => write!(f, "random"),
+            Difficulty::Greedy => write!(f, "greedy"),
+            Difficulty::Shallow => write!(f, "shallow"),
+            Difficulty::Perfect => write!(f, "perfect"),
+        }
+    }
+}
+
+impl FromStr for Difficulty {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "random" => Ok(Difficulty::Random),
+            "greedy" => Ok(Difficulty::Greedy),
+            "shallow" => Ok(Difficulty::Shallow),
+            "perfect" => Ok(Difficulty::Perfect),
+            _ => Err(())
+        }
+    }
+}
+
+//*
+// Computer player sitting in players[1] of a session, it replies right after the human moves
+// */
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Bot {
+    difficulty: Difficulty,
+}
+
+impl Bot {
+    pub const USERNAME: &'static str = "Computer";
+
+    //Roughly how many positions the bot can look at for one move
+    const NODE_BUDGET: f64 = 100_000.0;
+    const GREEDY_DEPTH: usize = 2;
+    const SHALLOW_DEPTH: usize = 4;
+    //Past this many legal moves alpha-beta can't look far enough, shallow and perfect switch to MCTS
+    const MCTS_BRANCHING: usize = 50;
+
+    pub fn new(difficulty: Difficulty) -> Self {
+        Bot {
+            difficulty
+        }
+    }
+
+    pub fn player() -> Player {
+        Player::new(Self::USERNAME.to_string(), String::new())
+    }
+
+    pub fn difficulty(&self) -> Difficulty { self.difficulty }
+
+    //*
+    // Classic 3x3 XO is small enough to be solved, so the bot plays perfectly there. On bigger games
+    // the search stops at the depth that keeps it around NODE_BUDGET positions, always deeper than greedy.
+    // The depth depends on the moves left, so it's worked out again for every move
+    // */
+    fn search_depth(game: &AnyGame) -> Option<usize> {
+        if let AnyGame::XO(xo) = game {
+            let (rows, cols, _) = xo.size();
+            if rows * cols <= 9 {
+                return None;
+            }
+        }
+        let branching = game.legal_moves().len().max(2) as f64;
+        Some(((Self::NODE_BUDGET.ln() / branching.ln()) as usize).max(Self::GREEDY_DEPTH + 1))
+    }
+
+    //None to search until the end of the game. Shallow always stops before perfect
+    fn depth(&self, game: &AnyGame) -> Option<usize> {
+        match self.difficulty {
+            Difficulty::Random => Some(0),
+            Difficulty::Greedy => Some(Self::GREEDY_DEPTH),
+            Difficulty::Shallow => Some(Self::search_depth(game).map_or(Self::SHALLOW_DEPTH, |depth| {
+                (depth - 1).min(Self::SHALLOW_DEPTH)
+            })),
+            Difficulty::Perfect => Self::search_depth(game),
+        }
+    }
+
+    fn mcts_config(&self) -> MctsConfig {
+        match self.difficulty {
+            Difficulty::Perfect => MctsConfig::new(50_000, Some(Duration::from_secs(1))),
+            _ => MctsConfig::new(2_000, Some(Duration::from_millis(200))),
+        }
+    }
+
+    //Pick one of the best moves at random so the bot doesn't play the same game every time
+    pub fn choose_move(&self, game: &AnyGame) -> Option<usize> {
+        let mut rng = rand::thread_rng();
+        match self.difficulty {
+            Difficulty::Random => return game.legal_moves().choose(&mut rng).copied(),
+            Difficulty::Shallow | Difficulty::Perfect if game.legal_moves().len() > Self::MCTS_BRANCHING => {
+                return mcts::best_move(game, &self.mcts_config());
+            }
+            _ => {}
+        }
+
+        let scores = alpha_beta::score_moves(game, self.depth(game));
+        let best = scores.iter().map(|(_, score)| *score).max()?;
+        let best_moves: Vec<usize> = scores.into_iter()
+            .filter(|(_, score)| *score == best)
+            .map(|(player_input, _)| player_input)
+            .collect();
+        best_moves.choose(&mut rng).copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::game::{AnyGame, Game};
+    use crate::game::connect_four::ConnectFour;
+    use crate::game::testing::never_loses;
+    use crate::game::ultimate_xo::UltimateXO;
+    use crate::game::xo::XO;
+    use super::{Bot, Difficulty};
+
+    //Classic XO after the inputs
+    fn play(inputs: &[usize]) -> AnyGame {
+        let mut game = AnyGame::XO(XO::new());
+        crate::game::testing::play(&mut game, inputs);
+        game
+    }
+
+    #[test]
+    fn difficulty_names_round_trip() {
+        for difficulty in [Difficulty::Random, Difficulty::Greedy, Difficulty::Shallow, Difficulty::Perfect] {
+            assert_eq!(difficulty.to_string().parse::<Difficulty>(), Ok(difficulty));
+        }
+        assert!("Perfect".parse::<Difficulty>().is_err());
+    }
+
+    #[test]
+    fn perfect_bot_never_loses_classic_xo() {
+        let game = AnyGame::XO(XO::new());
+        let bot = Bot::new(Difficulty::Perfect);
+        let bot_moves = |game: &AnyGame| vec![bot.choose_move(game).unwrap()];
+        assert!(never_loses(&game, true, &bot_moves));
+        assert!(never_loses(&game, false, &bot_moves));
+    }
+
+    #[test]
+    fn greedy_bot_wins_now_then_blocks() {
+        let bot = Bot::new(Difficulty::Greedy);
+        //X on 1 and 2, O on 4 and 5
+        assert_eq!(bot.choose_move(&play(&[1, 4, 2, 5])), Some(3));
+        //X on 1 and 2, O on 5 has to block 3
+        assert_eq!(bot.choose_move(&play(&[1, 5, 2])), Some(3));
+    }
+
+    #[test]
+    fn random_bot_plays_a_legal_move() {
+        let game = play(&[1, 5, 9]);
+        let bot = Bot::new(Difficulty::Random);
+        for _ in 0..20 {
+            assert!(game.legal_moves().contains(&bot.choose_move(&game).unwrap()));
+        }
+        assert_eq!(bot.choose_move(&play(&[1, 4, 2, 5, 3])), None);
+    }
+
+    #[test]
+    fn search_depth_is_bounded_past_3x3() {
+        assert_eq!(Bot::search_depth(&AnyGame::XO(XO::new())), None);
+        let depth = Bot::search_depth(&AnyGame::ConnectFour(ConnectFour::new())).unwrap();
+        assert!(depth >= Bot::SHALLOW_DEPTH);
+        assert!(Bot::new(Difficulty::Shallow).depth(&AnyGame::XO(XO::gomoku())).unwrap() <= Bot::SHALLOW_DEPTH);
+    }
+
+    //Greedy, shallow and perfect depths for the game
+    fn depths(game: &AnyGame) -> [Option<usize>; 3] {
+        [Difficulty::Greedy, Difficulty::Shallow, Difficulty::Perfect].map(|difficulty| Bot::new(difficulty).depth(game))
+    }
+
+    #[test]
+    fn levels_search_deeper_and_deeper_past_3x3() {
+        let [greedy, shallow, perfect] = depths(&AnyGame::XO(XO::with_size(4, 4, 3)));
+        assert!(greedy < shallow && shallow < perfect, "{:?} {:?} {:?}", greedy, shallow, perfect);
+        assert_eq!(depths(&AnyGame::XO(XO::new()))[2], None);
+    }
+
+    #[test]
+    fn depth_follows_the_moves_left() {
+        let mut game = AnyGame::UltimateXO(UltimateXO::new());
+        let opening = depths(&game);
+        //sent to one small board, 9 moves left to look at
+        game.make_a_move(55).unwrap();
+        let [greedy, shallow, perfect] = depths(&game);
+        assert!(greedy < shallow && shallow < perfect, "{:?} {:?} {:?}", greedy, shallow, perfect);
+        assert!(perfect > opening[2]);
+    }
+}
@@ -0,0 +1,277 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use tokio::sync::RwLock;
+use warp::Filter;
+use crate::dao::{DAO, Database};
+use crate::error::Error;
+use crate::model::player::Player;
+
+pub mod password;
+pub mod rate_limit;
+pub mod validation;
+
+type HmacSha256 = Hmac<Sha256>;
+
+//*
+// What a token says about its owner. The token is the base64 of these claims followed by the base64
+// of their HMAC-SHA256, so the server can check it without storing it
+// */
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct Claims {
+    pub username: String,
+    //A username can be given up and taken by someone else, the player id stays with the player
+    pub player_id: i32,
+    //Seconds since epoch
+    pub expires_at: u64,
+    //Milliseconds since epoch, to revoke every token of a player issued before some time
+    issued_at: u64,
+    #[serde(default)]
+    pub guest: bool,
+    //Random id so a single token can be revoked
+    id: u64,
+}
+
+impl Claims {
+    //Password is left empty, the token already proved who the player is
+    pub fn player(&self) -> Player {
+        Player::new(self.username.clone(), String::new())
+    }
+}
+
+#[derive(Serialize)]
+pub struct Token {
+    pub token: String,
+    pub token_type: &'static str,
+    pub expires_at: u64,
+}
+
+//*
+// Issue, check and revoke bearer tokens. Revoked ids are kept in memory until the token would have
+// expired anyway
+// */
+#[derive(Clone)]
+pub struct TokenService {
+    key: Arc<Vec<u8>>,
+    lifetime: Duration,
+    revoked: Arc<RwLock<HashMap<u64, u64>>>,
+    //Username to the time in milliseconds before which all their tokens are revoked
+    revoked_players: Arc<RwLock<HashMap<String, u64>>>,
+}
+
+impl TokenService {
+    pub const DEFAULT_LIFETIME: Duration = Duration::from_secs(24 * 60 * 60);
+
+    pub fn new(key: Vec<u8>, lifetime: Duration) -> Self {
+        TokenService {
+            key: Arc::new(key),
+            lifetime,
+            revoked: Arc::new(RwLock::new(HashMap::new())),
+            revoked_players: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+
+    //*
+    // Key from XOGAMEDEV_TOKEN_SECRET so tokens survive a restart, a random one otherwise and
+    // everyone has to log in again after a restart
+    // */
+    pub fn from_env() -> Self {
+        let key = match std::env::var("XOGAMEDEV_TOKEN_SECRET") {
+            Ok(secret) if !secret.is_empty() => secret.into_bytes(),
+            _ => (0..32).map(|_| rand::random::<u8>()).collect()
+        };
+        Self::new(key, Self::DEFAULT_LIFETIME)
+    }
+
+    pub fn lifetime(&self) -> Duration {
+        self.lifetime
+    }
+
+    pub fn issue(&self, username: String, player_id: i32) -> Token {
+        self.issue_claims(username, player_id, false)
+    }
+
+    pub fn issue_guest(&self, username: String, player_id: i32) -> Token {
+        self.issue_claims(username, player_id, true)
+    }
+
+    fn issue_claims(&self, username: String, player_id: i32, guest: bool) -> Token {
+        let claims = Claims {
+            username,
+            player_id,
+            expires_at: now() + self.lifetime.as_secs(),
+            issued_at: now_millis(),
+            guest,
+            id: rand::random(),
+        };
+        let payload = URL_SAFE_NO_PAD.encode(serde_json::to_vec(&claims).unwrap());
+        let signature = URL_SAFE_NO_PAD.encode(self.mac(&payload).finalize().into_bytes());
+        Token {
+            token: format!("{}.{}", payload, signature),
+            token_type: "Bearer",
+            expires_at: claims.expires_at,
+        }
+    }
+
+    pub async fn verify(&self, token: &str) -> Result<Claims, Error> {
+        let (payload, signature) = token.split_once('.').ok_or(Error::Unauthorized)?;
+        let signature = URL_SAFE_NO_PAD.decode(signature).map_err(|_| Error::Unauthorized)?;
+        self.mac(payload).verify_slice(&signature).map_err(|_| Error::Unauthorized)?;
+
+        let claims = URL_SAFE_NO_PAD.decode(payload).ok()
+            .and_then(|claims| serde_json::from_slice::<Claims>(&claims).ok())
+            .ok_or(Error::Unauthorized)?;
+        if claims.expires_at <= now() || self.revoked.read().await.contains_key(&claims.id) {
+            return Err(Error::Unauthorized);
+        }
+        match self.revoked_players.read().await.get(&claims.username) {
+            Some(revoked_at) if claims.issued_at < *revoked_at => Err(Error::Unauthorized),
+            _ => Ok(claims)
+        }
+    }
+
+    pub async fn revoke(&self, claims: &Claims) {
+        let mut revoked = self.revoked.write().await;
+        let now = now();
+        revoked.retain(|_, expires_at| *expires_at > now);
+        revoked.insert(claims.id, claims.expires_at);
+    }
+
+    //*
+    // Log the player out everywhere, after a password change, a rename or a deleted account.
+    // Tokens issued from now on are still valid
+    // */
+    pub async fn revoke_player(&self, username: String) {
+        let mut revoked_players = self.revoked_players.write().await;
+        let now = now_millis();
+        let lifetime = self.lifetime.as_millis() as u64;
+        revoked_players.retain(|_, revoked_at| *revoked_at + lifetime > now);
+        revoked_players.insert(username, now);
+    }
+
+    fn mac(&self, payload: &str) -> HmacSha256 {
+        let mut mac = HmacSha256::new_from_slice(&self.key).expect("HMAC takes keys of any size");
+        mac.update(payload.as_bytes());
+        mac
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as u64
+}
+
+//*
+// Claims of a valid token whose player still has the username it was issued for. Revoked tokens are only
+// remembered until a restart, a renamed or deleted player is caught here after one too
+// */
+pub async fn verify_player(tokens: &TokenService, token: &str, dao: &DAO<impl Database>) -> Result<Claims, Error> {
+    let claims = tokens.verify(token).await?;
+    match dao.player_id(claims.username.clone()).await {
+        Ok(Some(player_id)) if player_id == claims.player_id => Ok(claims),
+        Ok(_) => Err(Error::Unauthorized),
+        Err(e) => Err(Error::DatabaseError(e))
+    }
+}
+
+//Claims of the bearer token in the Authorization header, rejects with Unauthorized without a valid one
+pub fn claims<D: Database + Clone + Send + 'static>(
+    tokens: TokenService, dao: DAO<D>
+) -> impl Filter<Extract = (Claims,), Error = warp::Rejection> + Clone {
+    warp::header::optional::<String>("authorization")
+        .and_then(move |header: Option<String>| {
+            let tokens = tokens.clone();
+            let dao = dao.clone();
+            async move {
+                let token = header.as_deref()
+                    .and_then(|header| header.strip_prefix("Bearer "))
+                    .ok_or(warp::reject::custom(Error::Unauthorized))?;
+                verify_player(&tokens, token.trim(), &dao).await.map_err(warp::reject::custom)
+            }
+        })
+}
+
+//Player logged in with the bearer token of the request
+pub fn authenticated<D: Database + Clone + Send + 'static>(
+    tokens: TokenService, dao: DAO<D>
+) -> impl Filter<Extract = (Player,), Error = warp::Rejection> + Clone {
+    claims(tokens, dao).map(|claims: Claims| claims.player())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use super::TokenService;
+
+    fn service() -> TokenService {
+        TokenService::new(b"test key".to_vec(), TokenService::DEFAULT_LIFETIME)
+    }
+
+    #[tokio::test]
+    async fn issued_token_is_verified() {
+        let tokens = service();
+        let token = tokens.issue("alice".to_string(), 7);
+        let claims = tokens.verify(&token.token).await.unwrap();
+        assert_eq!(claims.username, "alice");
+        assert_eq!(claims.player_id, 7);
+        assert_eq!(claims.expires_at, token.expires_at);
+        assert!(!claims.guest);
+        assert!(tokens.verify(&tokens.issue_guest("guest-1".to_string(), 8).token).await.unwrap().guest);
+    }
+
+    #[tokio::test]
+    async fn token_signed_with_another_key_or_changed_is_refused() {
+        let tokens = service();
+        let other = TokenService::new(b"other key".to_vec(), TokenService::DEFAULT_LIFETIME);
+        assert!(tokens.verify(&other.issue("alice".to_string(), 7).token).await.is_err());
+
+        let token = tokens.issue("alice".to_string(), 7).token;
+        let (_, signature) = token.split_once('.').unwrap();
+        //claims of another token with the signature of this one
+        let forged = tokens.issue("mallory".to_string(), 9).token;
+        let (payload, _) = forged.split_once('.').unwrap();
+        assert!(tokens.verify(&format!("{}.{}", payload, signature)).await.is_err());
+        assert!(tokens.verify("not a token").await.is_err());
+        assert!(tokens.verify("").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn expired_token_is_refused() {
+        let tokens = TokenService::new(b"test key".to_vec(), Duration::ZERO);
+        assert!(tokens.verify(&tokens.issue("alice".to_string(), 7).token).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn revoked_token_is_refused_and_others_still_work() {
+        let tokens = service();
+        let token = tokens.issue("alice".to_string(), 7).token;
+        let other = tokens.issue("alice".to_string(), 7).token;
+        let claims = tokens.verify(&token).await.unwrap();
+        tokens.revoke(&claims).await;
+        assert!(tokens.verify(&token).await.is_err());
+        assert!(tokens.verify(&other).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn revoking_a_player_keeps_tokens_issued_after() {
+        let tokens = service();
+        let before = tokens.issue("alice".to_string(), 7).token;
+        let bob = tokens.issue("bob".to_string(), 8).token;
+        //issue times are in milliseconds
+        tokio::time::sleep(Duration::from_millis(2)).await;
+        tokens.revoke_player("alice".to_string()).await;
+        tokio::time::sleep(Duration::from_millis(2)).await;
+        let after = tokens.issue("alice".to_string(), 7).token;
+        assert!(tokens.verify(&before).await.is_err());
+        assert!(tokens.verify(&bob).await.is_ok());
+        assert!(tokens.verify(&after).await.is_ok());
+    }
+}
@@ -0,0 +1,63 @@
+use argon2::{Algorithm, Argon2, PasswordHash, PasswordHasher, PasswordVerifier};
+use argon2::password_hash::rand_core::OsRng;
+use argon2::password_hash::SaltString;
+
+#[derive(PartialEq, Eq, Debug)]
+pub enum Verification {
+    Valid,
+    //Right password but still saved in plaintext, it should be hashed now that we know it
+    ValidPlaintext,
+    Invalid,
+}
+
+//Argon2id PHC string with its own random salt, what goes in the password column
+pub fn hash(password: &str) -> String {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .expect("Argon2 with default params accepts any password")
+        .to_string()
+}
+
+//*
+// Check a password against the password column. Rows from before hashing hold the plaintext password,
+// anything that isn't an Argon2 PHC string is one of them. A plaintext password like $secret parses
+// as a PHC string of an unknown algorithm, it's still plaintext
+// */
+pub fn verify(password: &str, stored: &str) -> Verification {
+    match PasswordHash::new(stored).ok().filter(|hash| Algorithm::try_from(hash.algorithm).is_ok()) {
+        Some(hash) if Argon2::default().verify_password(password.as_bytes(), &hash).is_ok() => Verification::Valid,
+        Some(_) => Verification::Invalid,
+        None if password == stored => Verification::ValidPlaintext,
+        None => Verification::Invalid
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{hash, verify, Verification};
+
+    #[test]
+    fn hashed_password_is_verified() {
+        let stored = hash("correct horse");
+        assert!(stored.starts_with("$argon2id$"));
+        assert_eq!(verify("correct horse", &stored), Verification::Valid);
+        assert_eq!(verify("wrong horse", &stored), Verification::Invalid);
+        //the hash itself typed as the password
+        assert_eq!(verify(&stored, &stored), Verification::Invalid);
+    }
+
+    #[test]
+    fn same_password_gets_a_new_salt() {
+        assert_ne!(hash("correct horse"), hash("correct horse"));
+    }
+
+    #[test]
+    fn plaintext_row_asks_for_a_rehash() {
+        assert_eq!(verify("hunter2", "hunter2"), Verification::ValidPlaintext);
+        assert_eq!(verify("hunter3", "hunter2"), Verification::Invalid);
+        //parses as a PHC string of an algorithm we never used
+        assert_eq!(verify("$ecret", "$ecret"), Verification::ValidPlaintext);
+        assert_eq!(verify("$ecret", "$secret"), Verification::Invalid);
+    }
+}
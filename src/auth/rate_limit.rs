@@ -0,0 +1,410 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::RwLock;
+use warp::Filter;
+use crate::error::Error;
+use crate::model::player::Player;
+
+//*
+// Recent attempts under one key, times are milliseconds since epoch so any store can keep them
+// */
+#[derive(Clone, Default, Debug)]
+pub struct Attempts {
+    pub times: Vec<u64>,
+    pub locked_until: Option<u64>,
+}
+
+impl Attempts {
+    fn is_empty(&self, now: u64) -> bool {
+        self.times.is_empty() && self.locked_until.is_none_or(|until| until <= now)
+    }
+}
+
+//*
+// Where attempts are kept. The in memory store is lost on restart and not shared between servers,
+// a database store only has to implement these two.
+// Requests for the same key run at the same time, so update has to change the attempts in one step
+// with nothing else reading or writing the key in between, a database store would lock the row
+// */
+pub trait AttemptStore: Clone + Send + Sync {
+    fn get(&self, key: &str) -> impl Future<Output = Attempts> + Send;
+    fn update<R: Send>(
+        &self, key: &str, change: impl FnOnce(&mut Attempts) -> R + Send
+    ) -> impl Future<Output = R> + Send;
+}
+
+#[derive(Clone, Default)]
+pub struct MemoryAttemptStore {
+    attempts: Arc<RwLock<HashMap<String, Attempts>>>,
+}
+
+impl MemoryAttemptStore {
+    //Past this many keys the ones with nothing left to remember are dropped
+    const SWEEP_SIZE: usize = 10_000;
+}
+
+impl AttemptStore for MemoryAttemptStore {
+    async fn get(&self, key: &str) -> Attempts {
+        self.attempts.read().await.get(key).cloned().unwrap_or_default()
+    }
+
+    async fn update<R: Send>(&self, key: &str, change: impl FnOnce(&mut Attempts) -> R + Send) -> R {
+        let mut map = self.attempts.write().await;
+        let now = now();
+        let mut attempts = map.remove(key).unwrap_or_default();
+        let result = change(&mut attempts);
+        if !attempts.is_empty(now) {
+            map.insert(key.to_string(), attempts);
+        }
+        if map.len() > Self::SWEEP_SIZE {
+            map.retain(|_, attempts| !attempts.is_empty(now));
+        }
+        result
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct LoginRules {
+    //Any attempt counts, so one address can't try passwords faster than this
+    pub attempts_per_ip: usize,
+    pub attempt_window: Duration,
+    //Failures before the username or the address is locked out
+    pub failures_per_username: usize,
+    pub failures_per_ip: usize,
+    pub failure_window: Duration,
+    pub lockout: Duration,
+}
+
+impl Default for LoginRules {
+    fn default() -> Self {
+        LoginRules {
+            attempts_per_ip: 10,
+            attempt_window: Duration::from_secs(60),
+            failures_per_username: 5,
+            failures_per_ip: 20,
+            failure_window: Duration::from_secs(15 * 60),
+            lockout: Duration::from_secs(15 * 60),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct RateLimiter<S: AttemptStore> {
+    store: S,
+    rules: LoginRules,
+}
+
+impl<S: AttemptStore> RateLimiter<S> {
+    pub fn new(store: S, rules: LoginRules) -> Self {
+        RateLimiter {
+            store,
+            rules,
+        }
+    }
+
+    //*
+    // Refuse the attempt if the username or the address is locked out or the address tries too often,
+    // otherwise count it
+    // */
+    async fn check(&self, ip: &str, username: &str) -> Result<(), Error> {
+        let now = now();
+        for key in [username_key(username), ip_failures_key(ip)] {
+            if let Some(until) = self.store.get(&key).await.locked_until.filter(|until| *until > now) {
+                return Err(too_many_attempts(until, now));
+            }
+        }
+        self.check_address(ip).await
+    }
+
+    //Counts toward the attempts of the address without looking at any username
+    async fn check_address(&self, ip: &str) -> Result<(), Error> {
+        let now = now();
+        let window = self.rules.attempt_window.as_millis() as u64;
+        let max = self.rules.attempts_per_ip;
+        self.store.update(&ip_attempts_key(ip), |attempts| {
+            attempts.times.retain(|time| time + window > now);
+            if attempts.times.len() >= max {
+                return Err(too_many_attempts(attempts.times[0] + window, now));
+            }
+            attempts.times.push(now);
+            Ok(())
+        }).await
+    }
+
+    async fn record_failure(&self, ip: &str, username: &str) {
+        self.add_failure(&username_key(username), self.rules.failures_per_username).await;
+        self.add_failure(&ip_failures_key(ip), self.rules.failures_per_ip).await;
+    }
+
+    async fn record_success(&self, username: &str) {
+        self.store.update(&username_key(username), |attempts| *attempts = Attempts::default()).await;
+    }
+
+    async fn add_failure(&self, key: &str, max: usize) {
+        let now = now();
+        let window = self.rules.failure_window.as_millis() as u64;
+        let lockout = self.rules.lockout.as_millis() as u64;
+        self.store.update(key, |attempts| {
+            attempts.times.retain(|time| time + window > now);
+            attempts.times.push(now);
+            if attempts.times.len() >= max {
+                attempts.times.clear();
+                attempts.locked_until = Some(now + lockout);
+            }
+        }).await;
+    }
+}
+
+//*
+// A login the rate limiter let through, the handler reports how it went
+// */
+pub struct LoginAttempt<S: AttemptStore> {
+    limiter: RateLimiter<S>,
+    ip: String,
+    username: String,
+}
+
+impl<S: AttemptStore> LoginAttempt<S> {
+    pub async fn failed(&self) {
+        self.limiter.record_failure(&self.ip, &self.username).await;
+    }
+
+    pub async fn succeeded(&self) {
+        self.limiter.record_success(&self.username).await;
+    }
+}
+
+//*
+// Goes in front of login in place of the body filter, rejects with TooManyAttempts before the password
+// is even looked at
+// */
+pub fn guard<S: AttemptStore + 'static>(
+    limiter: RateLimiter<S>
+) -> impl Filter<Extract = (Player, LoginAttempt<S>), Error = warp::Rejection> + Clone {
+    warp::addr::remote()
+        .and(warp::body::json())
+        .and_then(move |address: Option<SocketAddr>, player: Player| {
+            let limiter = limiter.clone();
+            async move {
+                let ip = remote_ip(address);
+                let username = player.get_username();
+                limiter.check(&ip, &username).await.map_err(warp::reject::custom)?;
+                Ok::<_, warp::Rejection>((player, LoginAttempt { limiter, ip, username }))
+            }
+        })
+        .untuple_one()
+}
+
+//*
+// Same limits for routes asking a logged in player for their password again, a stolen token shouldn't be
+// a way to guess it. Takes the place of the filter of the logged in player
+// */
+pub fn player_guard<S: AttemptStore + 'static>(
+    player: impl Filter<Extract = (Player,), Error = warp::Rejection> + Clone + Send + Sync + 'static,
+    limiter: RateLimiter<S>
+) -> impl Filter<Extract = (Player, LoginAttempt<S>), Error = warp::Rejection> + Clone {
+    warp::addr::remote()
+        .and(player)
+        .and_then(move |address: Option<SocketAddr>, player: Player| {
+            let limiter = limiter.clone();
+            async move {
+                let ip = remote_ip(address);
+                let username = player.get_username();
+                limiter.check(&ip, &username).await.map_err(warp::reject::custom)?;
+                Ok::<_, warp::Rejection>((player, LoginAttempt { limiter, ip, username }))
+            }
+        })
+        .untuple_one()
+}
+
+//*
+// Same cap on attempts per address for routes without a username, guest login makes a player every
+// time so it can't be free
+// */
+pub fn address_guard<S: AttemptStore + 'static>(
+    limiter: RateLimiter<S>
+) -> impl Filter<Extract = (), Error = warp::Rejection> + Clone {
+    warp::addr::remote()
+        .and_then(move |address: Option<SocketAddr>| {
+            let limiter = limiter.clone();
+            async move {
+                limiter.check_address(&remote_ip(address)).await.map_err(warp::reject::custom)
+            }
+        })
+        .untuple_one()
+}
+
+fn remote_ip(address: Option<SocketAddr>) -> String {
+    address.map_or("unknown".to_string(), |address| address.ip().to_string())
+}
+
+fn too_many_attempts(until: u64, now: u64) -> Error {
+    //Round up so clients don't come back a moment too early
+    Error::TooManyAttempts { retry_after: (until - now).div_ceil(1000) }
+}
+
+fn username_key(username: &str) -> String {
+    format!("username:{}", username)
+}
+
+fn ip_failures_key(ip: &str) -> String {
+    format!("ip-failures:{}", ip)
+}
+
+fn ip_attempts_key(ip: &str) -> String {
+    format!("ip:{}", ip)
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use tokio::task::JoinSet;
+    use crate::error::Error;
+    use super::{guard, AttemptStore, Attempts, LoginRules, MemoryAttemptStore, RateLimiter};
+
+    //*
+    // Memory store that waits before every access like a database would, so requests running at the same
+    // time get to interleave
+    // */
+    #[derive(Clone, Default)]
+    struct SlowStore(MemoryAttemptStore);
+
+    impl AttemptStore for SlowStore {
+        async fn get(&self, key: &str) -> Attempts {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+            self.0.get(key).await
+        }
+
+        async fn update<R: Send>(&self, key: &str, change: impl FnOnce(&mut Attempts) -> R + Send) -> R {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+            self.0.update(key, change).await
+        }
+    }
+
+    fn limiter() -> RateLimiter<MemoryAttemptStore> {
+        RateLimiter::new(MemoryAttemptStore::default(), LoginRules {
+            attempts_per_ip: 100,
+            ..LoginRules::default()
+        })
+    }
+
+    #[tokio::test]
+    async fn username_is_locked_out_after_too_many_failures() {
+        let limiter = limiter();
+        for _ in 0..5 {
+            limiter.check("10.0.0.1", "alice").await.unwrap();
+            limiter.record_failure("10.0.0.1", "alice").await;
+        }
+        //from any address, until the lockout is over
+        match limiter.check("10.0.0.2", "alice").await {
+            Err(Error::TooManyAttempts { retry_after }) => assert!(retry_after > 14 * 60 && retry_after <= 15 * 60),
+            _ => panic!("alice should be locked out"),
+        }
+        assert!(limiter.check("10.0.0.1", "bob").await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn success_forgets_the_failures() {
+        let limiter = limiter();
+        for _ in 0..4 {
+            limiter.record_failure("10.0.0.1", "alice").await;
+        }
+        limiter.record_success("alice").await;
+        for _ in 0..4 {
+            limiter.record_failure("10.0.0.1", "alice").await;
+        }
+        assert!(limiter.check("10.0.0.1", "alice").await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn address_is_locked_out_after_failures_on_many_usernames() {
+        let limiter = limiter();
+        for i in 0..20 {
+            limiter.record_failure("10.0.0.1", &format!("player{}", i)).await;
+        }
+        assert!(matches!(limiter.check("10.0.0.1", "someone").await, Err(Error::TooManyAttempts { .. })));
+        assert!(limiter.check("10.0.0.2", "someone").await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn address_can_only_try_so_often() {
+        let limiter = RateLimiter::new(MemoryAttemptStore::default(), LoginRules {
+            attempts_per_ip: 3,
+            attempt_window: Duration::from_secs(60),
+            ..LoginRules::default()
+        });
+        for i in 0..3 {
+            limiter.check("10.0.0.1", &format!("player{}", i)).await.unwrap();
+        }
+        match limiter.check("10.0.0.1", "player3").await {
+            Err(Error::TooManyAttempts { retry_after }) => assert!(retry_after > 0 && retry_after <= 60),
+            _ => panic!("the address should be over its attempts"),
+        }
+    }
+
+    #[tokio::test]
+    async fn guard_rejects_before_the_handler() {
+        let limiter = limiter();
+        for _ in 0..5 {
+            limiter.record_failure("10.0.0.1", "alice").await;
+        }
+        let filter = guard(limiter);
+        let rejection = warp::test::request()
+            .method("POST")
+            .remote_addr("10.0.0.1:4000".parse().unwrap())
+            .json(&serde_json::json!({ "username": "alice", "password": "wrong" }))
+            .filter(&filter)
+            .await
+            .err()
+            .unwrap();
+        assert!(matches!(rejection.find::<Error>(), Some(Error::TooManyAttempts { .. })));
+
+        let (player, _) = warp::test::request()
+            .method("POST")
+            .remote_addr("10.0.0.1:4000".parse().unwrap())
+            .json(&serde_json::json!({ "username": "bob", "password": "secret" }))
+            .filter(&filter)
+            .await
+            .unwrap();
+        assert_eq!(player.get_username(), "bob");
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn concurrent_attempts_are_all_counted() {
+        let limiter = RateLimiter::new(SlowStore::default(), LoginRules {
+            attempts_per_ip: 10,
+            ..LoginRules::default()
+        });
+        let mut checks = JoinSet::new();
+        for i in 0..50 {
+            let limiter = limiter.clone();
+            checks.spawn(async move { limiter.check("10.0.0.1", &format!("player{}", i)).await.is_ok() });
+        }
+        let mut passed = 0;
+        while let Some(result) = checks.join_next().await {
+            passed += result.unwrap() as usize;
+        }
+        assert_eq!(passed, 10);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn concurrent_failures_lock_the_username_out() {
+        let limiter = RateLimiter::new(SlowStore::default(), LoginRules::default());
+        let mut failures = JoinSet::new();
+        for i in 0..5 {
+            let limiter = limiter.clone();
+            failures.spawn(async move { limiter.record_failure(&format!("10.0.0.{}", i), "alice").await });
+        }
+        while let Some(result) = failures.join_next().await {
+            result.unwrap();
+        }
+        assert!(matches!(limiter.check("10.0.0.9", "alice").await, Err(Error::TooManyAttempts { .. })));
+    }
+}
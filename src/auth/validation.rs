@@ -0,0 +1,172 @@
+use crate::ai::Bot;
+use crate::error::Error;
+use crate::model::player::Player;
+
+//*
+// What a username and a password need to be accepted, checked on registration and account changes.
+// Login doesn't check them so accounts made under older rules can still log in
+// */
+#[derive(Clone, Debug)]
+pub struct ValidationRules {
+    pub username_min: usize,
+    pub username_max: usize,
+    //Compared without case
+    pub reserved_usernames: Vec<String>,
+    pub reserved_prefixes: Vec<String>,
+    pub password_min: usize,
+    //Argon2 hashes the whole password, don't let anyone send megabytes of it
+    pub password_max: usize,
+    //Kinds of characters among lowercase, uppercase, digits and symbols
+    pub password_min_classes: usize,
+}
+
+impl Default for ValidationRules {
+    fn default() -> Self {
+        ValidationRules {
+            username_min: 3,
+            username_max: 24,
+            reserved_usernames: vec![
+                Bot::USERNAME.to_string(),
+                Player::DELETED_USERNAME.to_string(),
+                "admin".to_string(),
+                "system".to_string(),
+            ],
+            reserved_prefixes: vec![Player::GUEST_PREFIX.to_string()],
+            password_min: 8,
+            password_max: 128,
+            password_min_classes: 2,
+        }
+    }
+}
+
+impl ValidationRules {
+    //*
+    // Defaults overridden by XOGAMEDEV_USERNAME_MIN, XOGAMEDEV_USERNAME_MAX, XOGAMEDEV_PASSWORD_MIN,
+    // XOGAMEDEV_PASSWORD_MAX and XOGAMEDEV_PASSWORD_MIN_CLASSES. XOGAMEDEV_RESERVED_USERNAMES is a comma
+    // separated list added to the reserved usernames, the ones the server uses itself stay reserved
+    // */
+    pub fn from_env() -> Self {
+        let default = Self::default();
+        let mut reserved_usernames = default.reserved_usernames;
+        if let Ok(reserved) = std::env::var("XOGAMEDEV_RESERVED_USERNAMES") {
+            reserved_usernames.extend(reserved.split(',')
+                .map(str::trim)
+                .filter(|username| !username.is_empty())
+                .map(str::to_string));
+        }
+        ValidationRules {
+            username_min: env_usize("XOGAMEDEV_USERNAME_MIN", default.username_min),
+            username_max: env_usize("XOGAMEDEV_USERNAME_MAX", default.username_max),
+            reserved_usernames,
+            reserved_prefixes: default.reserved_prefixes,
+            password_min: env_usize("XOGAMEDEV_PASSWORD_MIN", default.password_min),
+            password_max: env_usize("XOGAMEDEV_PASSWORD_MAX", default.password_max),
+            password_min_classes: env_usize("XOGAMEDEV_PASSWORD_MIN_CLASSES", default.password_min_classes),
+        }
+    }
+
+    pub fn check_username(&self, username: &str) -> Result<(), Error> {
+        let length = username.chars().count();
+        if length < self.username_min {
+            return Err(Error::UsernameTooShort { min: self.username_min });
+        }
+        if length > self.username_max {
+            return Err(Error::UsernameTooLong { max: self.username_max });
+        }
+        if !username.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.')) {
+            return Err(Error::UsernameInvalidCharacters);
+        }
+        let lowercase = username.to_lowercase();
+        if self.reserved_usernames.iter().any(|reserved| reserved.eq_ignore_ascii_case(username))
+            || self.reserved_prefixes.iter().any(|prefix| lowercase.starts_with(&prefix.to_lowercase())) {
+            return Err(Error::UsernameReserved);
+        }
+        Ok(())
+    }
+
+    pub fn check_password(&self, password: &str) -> Result<(), Error> {
+        let length = password.chars().count();
+        if length < self.password_min {
+            return Err(Error::PasswordTooShort { min: self.password_min });
+        }
+        if length > self.password_max {
+            return Err(Error::PasswordTooLong { max: self.password_max });
+        }
+        let classes = [
+            password.chars().any(|c| c.is_lowercase()),
+            password.chars().any(|c| c.is_uppercase()),
+            password.chars().any(|c| c.is_ascii_digit()),
+            password.chars().any(|c| !c.is_alphanumeric()),
+        ];
+        if classes.iter().filter(|class| **class).count() < self.password_min_classes {
+            return Err(Error::PasswordTooWeak { min_classes: self.password_min_classes });
+        }
+        Ok(())
+    }
+
+    pub fn check(&self, player: &Player) -> Result<(), Error> {
+        self.check_username(&player.get_username())?;
+        self.check_password(&player.get_password())
+    }
+}
+
+//The default when the variable is missing or not a number
+fn env_usize(name: &str, default: usize) -> usize {
+    std::env::var(name).ok()
+        .and_then(|value| value.trim().parse().ok())
+        .unwrap_or(default)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::Error;
+    use super::{env_usize, ValidationRules};
+
+    #[test]
+    fn username_length_and_characters() {
+        let rules = ValidationRules::default();
+        assert!(rules.check_username("alice_99").is_ok());
+        assert!(rules.check_username("a.b-c").is_ok());
+        assert!(matches!(rules.check_username("al"), Err(Error::UsernameTooShort { min: 3 })));
+        assert!(matches!(rules.check_username(&"a".repeat(25)), Err(Error::UsernameTooLong { max: 24 })));
+        assert!(matches!(rules.check_username("alice bob"), Err(Error::UsernameInvalidCharacters)));
+        assert!(matches!(rules.check_username("élodie"), Err(Error::UsernameInvalidCharacters)));
+    }
+
+    #[test]
+    fn reserved_usernames_without_case() {
+        let rules = ValidationRules::default();
+        assert!(matches!(rules.check_username("computer"), Err(Error::UsernameReserved)));
+        assert!(matches!(rules.check_username("ADMIN"), Err(Error::UsernameReserved)));
+        assert!(matches!(rules.check_username("Guest-1234"), Err(Error::UsernameReserved)));
+        assert!(rules.check_username("guests").is_ok());
+    }
+
+    #[test]
+    fn password_length_and_kinds_of_characters() {
+        let rules = ValidationRules::default();
+        assert!(rules.check_password("password1").is_ok());
+        assert!(rules.check_password("Password").is_ok());
+        assert!(matches!(rules.check_password("pass1"), Err(Error::PasswordTooShort { min: 8 })));
+        assert!(matches!(rules.check_password(&"a1".repeat(65)), Err(Error::PasswordTooLong { max: 128 })));
+        assert!(matches!(rules.check_password("password"), Err(Error::PasswordTooWeak { min_classes: 2 })));
+        assert!(matches!(rules.check_password("12345678"), Err(Error::PasswordTooWeak { min_classes: 2 })));
+    }
+
+    #[test]
+    fn rules_can_be_changed() {
+        let rules = ValidationRules { password_min_classes: 4, username_min: 1, ..ValidationRules::default() };
+        assert!(rules.check_username("a").is_ok());
+        assert!(matches!(rules.check_password("password1A"), Err(Error::PasswordTooWeak { min_classes: 4 })));
+        assert!(rules.check_password("password1A!").is_ok());
+    }
+
+    #[test]
+    fn env_usize_falls_back_to_the_default() {
+        assert_eq!(env_usize("XOGAMEDEV_TEST_UNSET", 5), 5);
+        std::env::set_var("XOGAMEDEV_TEST_NUMBER", " 12 ");
+        assert_eq!(env_usize("XOGAMEDEV_TEST_NUMBER", 5), 12);
+        std::env::set_var("XOGAMEDEV_TEST_NOT_A_NUMBER", "twelve");
+        assert_eq!(env_usize("XOGAMEDEV_TEST_NOT_A_NUMBER", 5), 5);
+    }
+}
@@ -0,0 +1,156 @@
+use serde::{Deserialize, Serialize};
+use warp::http::StatusCode;
+use crate::auth::{Claims, Token, TokenService};
+use crate::auth::rate_limit::{AttemptStore, LoginAttempt};
+use crate::auth::validation::ValidationRules;
+use crate::dao::{DAO, Database};
+use crate::error::Error;
+use crate::error::Error::DatabaseError;
+use crate::model::player::Player;
+use crate::model::session::SessionList;
+
+#[derive(Deserialize)]
+pub struct ChangePasswordRequest {
+    current_password: String,
+    new_password: String,
+}
+
+#[derive(Deserialize)]
+pub struct RenameRequest {
+    username: String,
+}
+
+#[derive(Deserialize)]
+pub struct DeleteAccountRequest {
+    password: String,
+}
+
+#[derive(Deserialize)]
+pub struct UpgradeGuestRequest {
+    username: String,
+    password: String,
+}
+
+#[derive(Serialize)]
+pub struct Renamed {
+    player_id: i32,
+    username: String,
+    #[serde(flatten)]
+    token: Token,
+}
+
+//*
+// Every token of the player is revoked, the reply has a new one for the device that changed it
+// */
+pub async fn change_password(
+    player: Player, attempt: LoginAttempt<impl AttemptStore>, request: ChangePasswordRequest, dao: DAO<impl Database>, tokens: TokenService,
+    rules: ValidationRules
+) -> Result<impl warp::Reply, warp::Rejection> {
+    rules.check_password(&request.new_password).map_err(warp::reject::custom)?;
+    let player_id = check_password(&player, request.current_password, &attempt, &dao).await?;
+    dao.change_password(player.get_username(), request.new_password).await
+        .map_err(|e| warp::reject::custom(DatabaseError(e)))?;
+
+    tokens.revoke_player(player.get_username()).await;
+    Ok(warp::reply::json(&tokens.issue(player.get_username(), player_id)))
+}
+
+//*
+// New username for the same player id. Saved sessions and the sessions being played follow the player,
+// tokens with the old username are revoked. Guests get a username by upgrading their account instead
+// */
+pub async fn rename(
+    claims: Claims, request: RenameRequest, dao: DAO<impl Database>, tokens: TokenService,
+    rules: ValidationRules, active_sessions: SessionList
+) -> Result<impl warp::Reply, warp::Rejection> {
+    if claims.guest {
+        return Err(warp::reject::custom(Error::GuestAccount));
+    }
+    let player = claims.player();
+    rules.check_username(&request.username).map_err(warp::reject::custom)?;
+    let player_id = match dao.rename(player.get_username(), request.username.clone()).await {
+        Ok(Some(player_id)) => player_id,
+        Ok(None) => return Err(warp::reject::custom(Error::UsernameTaken)),
+        Err(e) => return Err(warp::reject::custom(DatabaseError(e)))
+    };
+    rename_in_sessions(&active_sessions, &player, &request.username).await;
+
+    tokens.revoke_player(player.get_username()).await;
+    Ok(warp::reply::json(&Renamed {
+        player_id,
+        username: request.username.clone(),
+        token: tokens.issue(request.username, player_id),
+    }))
+}
+
+//*
+// The account is gone but its games stay on the scoreboard under Player::DELETED_USERNAME
+// */
+pub async fn delete_account(
+    player: Player, attempt: LoginAttempt<impl AttemptStore>, request: DeleteAccountRequest, dao: DAO<impl Database>, tokens: TokenService,
+    active_sessions: SessionList
+) -> Result<impl warp::Reply, warp::Rejection> {
+    check_password(&player, request.password, &attempt, &dao).await?;
+    dao.delete_player(player.get_username()).await
+        .map_err(|e| warp::reject::custom(DatabaseError(e)))?;
+    rename_in_sessions(&active_sessions, &player, Player::DELETED_USERNAME).await;
+
+    tokens.revoke_player(player.get_username()).await;
+    Ok(warp::reply::with_status("Account deleted", StatusCode::OK))
+}
+
+//*
+// The guest picks a username and a password and becomes a registered player with the same player id.
+// Their games, saved and being played, move to the new username and count on the scoreboard from now on
+// */
+pub async fn upgrade_guest(
+    claims: Claims, request: UpgradeGuestRequest, dao: DAO<impl Database>, tokens: TokenService,
+    rules: ValidationRules, active_sessions: SessionList
+) -> Result<impl warp::Reply, warp::Rejection> {
+    if !claims.guest {
+        return Err(warp::reject::custom(Error::NotAGuest));
+    }
+    let player = Player::new(request.username, request.password);
+    rules.check(&player).map_err(warp::reject::custom)?;
+    let player_id = match dao.upgrade_guest(claims.username.clone(), player.clone()).await {
+        Ok(Some(player_id)) => player_id,
+        Ok(None) => return Err(warp::reject::custom(Error::UsernameTaken)),
+        Err(e) => return Err(warp::reject::custom(DatabaseError(e)))
+    };
+    rename_in_sessions(&active_sessions, &claims.player(), &player.get_username()).await;
+
+    tokens.revoke_player(claims.username).await;
+    Ok(warp::reply::json(&Renamed {
+        player_id,
+        username: player.get_username(),
+        token: tokens.issue(player.get_username(), player_id),
+    }))
+}
+
+//*
+// Sensitive changes need the password again, a stolen token alone is not enough. Wrong passwords count
+// toward the same lockout as login
+// */
+async fn check_password(
+    player: &Player, password: String, attempt: &LoginAttempt<impl AttemptStore>, dao: &DAO<impl Database>
+) -> Result<i32, warp::Rejection> {
+    match dao.login(Player::new(player.get_username(), password)).await {
+        Ok(Some(player_id)) => {
+            attempt.succeeded().await;
+            Ok(player_id)
+        }
+        Ok(None) => {
+            attempt.failed().await;
+            Err(warp::reject::custom(Error::AuthenticationFail))
+        }
+        Err(e) => Err(warp::reject::custom(DatabaseError(e)))
+    }
+}
+
+async fn rename_in_sessions(
+    active_sessions: &SessionList, player: &Player, new_username: &str
+) {
+    for session in active_sessions.read().await.values() {
+        session.write().await.rename_player(&player.get_username(), new_username);
+    }
+}
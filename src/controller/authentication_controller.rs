@@ -1,21 +1,89 @@
+use std::time::Duration;
 use crate::dao::Database;
+use log::{error, info};
+use serde::Serialize;
 use warp::http::StatusCode;
+use crate::auth::{Claims, Token, TokenService};
+use crate::auth::rate_limit::{AttemptStore, LoginAttempt};
+use crate::auth::validation::ValidationRules;
 use crate::model::player::Player;
 use crate::dao::DAO;
 use crate::error::Error;
 
-pub async fn register(player: Player, dao: DAO<impl Database>) -> Result<impl warp::Reply, warp::Rejection> {
+//Generated names rarely collide, give up after a few tries instead of looping
+const GUEST_NAME_TRIES: usize = 5;
+//How often guests nobody can log in as anymore are deleted
+const GUEST_PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Serialize)]
+pub struct GuestToken {
+    username: String,
+    #[serde(flatten)]
+    token: Token,
+}
+
+pub async fn register(player: Player, dao: DAO<impl Database>, rules: ValidationRules) -> Result<impl warp::Reply, warp::Rejection> {
+    rules.check(&player).map_err(warp::reject::custom)?;
     match dao.register(player).await {
         Ok(true) => Ok(warp::reply::with_status("Operation completed successfully", StatusCode::OK)),
-        Ok(false) => Err(warp::reject::custom(Error::AuthenticationFail)),
+        Ok(false) => Err(warp::reject::custom(Error::UsernameTaken)),
         Err(e) => Err(warp::reject::custom(Error::DatabaseError(e))),
     }
 }
 
-pub async fn login(player: Player, dao: DAO<impl Database>) -> Result<impl warp::Reply, warp::Rejection> {
-    match dao.login(player).await {
-        Ok(true) => Ok(warp::reply::with_status("Operation completed successfully", StatusCode::OK)),
-        Ok(false) => Err(warp::reject::custom(Error::AuthenticationFail)),
+//The token goes in the Authorization header of every session route: Bearer <token>
+pub async fn login(
+    player: Player, attempt: LoginAttempt<impl AttemptStore>, dao: DAO<impl Database>, tokens: TokenService
+) -> Result<impl warp::Reply, warp::Rejection> {
+    match dao.login(player.clone()).await {
+        Ok(Some(player_id)) => {
+            attempt.succeeded().await;
+            Ok(warp::reply::json(&tokens.issue(player.get_username(), player_id)))
+        }
+        Ok(None) => {
+            attempt.failed().await;
+            Err(warp::reject::custom(Error::AuthenticationFail))
+        }
         Err(e) => Err(warp::reject::custom(Error::DatabaseError(e)))
     }
-}
\ No newline at end of file
+}
+
+//*
+// Quick play without registering. The guest gets a generated name and a token, there is no password
+// to log in with again so they should upgrade the account to keep it
+// */
+pub async fn guest_login(dao: DAO<impl Database>, tokens: TokenService) -> Result<impl warp::Reply, warp::Rejection> {
+    for _ in 0..GUEST_NAME_TRIES {
+        let guest = Player::new_guest();
+        match dao.create_guest(guest.clone()).await {
+            Ok(Some(player_id)) => return Ok(warp::reply::json(&GuestToken {
+                username: guest.get_username(),
+                token: tokens.issue_guest(guest.get_username(), player_id),
+            })),
+            Ok(None) => continue,
+            Err(e) => return Err(warp::reject::custom(Error::DatabaseError(e)))
+        }
+    }
+    Err(warp::reject::custom(Error::UsernameTaken))
+}
+
+//*
+// A guest only ever has the token from guest login, once it expired nobody can use the account again.
+// Runs for as long as the server does
+// */
+pub async fn purge_expired_guests(dao: DAO<impl Database>, token_lifetime: Duration) {
+    let mut interval = tokio::time::interval(GUEST_PURGE_INTERVAL);
+    loop {
+        interval.tick().await;
+        match dao.delete_guests_older_than(token_lifetime).await {
+            Ok(0) => {}
+            Ok(deleted) => info!("Deleted {} expired guests", deleted),
+            Err(e) => error!("Failed to delete expired guests {}", e)
+        }
+    }
+}
+
+pub async fn logout(claims: Claims, tokens: TokenService) -> Result<impl warp::Reply, warp::Rejection> {
+    tokens.revoke(&claims).await;
+    Ok(warp::reply::with_status("Logged out", StatusCode::OK))
+}
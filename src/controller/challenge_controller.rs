@@ -0,0 +1,176 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use serde::Deserialize;
+use crate::controller::session_controller::{self, CreateSessionRequest};
+use crate::dao::{DAO, Database};
+use crate::error::Error;
+use crate::error::Error::{DatabaseError, SessionNotExist};
+use crate::game::AnyGame;
+use crate::model::event::SessionEvent;
+use crate::model::player::Player;
+use crate::model::session::{Session, SessionID, SessionList};
+use crate::model::view::SessionView;
+
+//How long the challenged player has to accept before the session is cleaned up
+const CHALLENGE_LIFETIME: Duration = Duration::from_secs(10 * 60);
+
+#[derive(Deserialize)]
+pub struct ChallengeRequest {
+    opponent: String,
+    //Same game options as a new session, except playing the computer
+    #[serde(flatten)]
+    session: CreateSessionRequest,
+}
+
+//*
+// Session waiting for one named player. Nobody else sees it or can join it, it goes away if they
+// decline or don't answer in time
+// */
+pub async fn create_challenge(
+    active_sessions: SessionList, player: Player,
+    request: ChallengeRequest, dao: DAO<impl Database>
+) -> Result<impl warp::Reply, warp::Rejection> {
+    if request.session.against_computer() || request.opponent == player.get_username() {
+        return Err(warp::reject::custom(Error::InvalidGameOption));
+    }
+    match dao.player_exists(request.opponent.clone()).await {
+        Ok(true) => {}
+        Ok(false) => return Err(warp::reject::custom(Error::PlayerNotExist)),
+        Err(e) => return Err(warp::reject::custom(DatabaseError(e)))
+    }
+    let game = request.session.new_game().map_err(warp::reject::custom)?;
+    let mut session = Session::new(player, game);
+    let expires_at = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() + CHALLENGE_LIFETIME.as_secs();
+    session.challenge(request.opponent, expires_at);
+    session_controller::insert_session(&active_sessions, &mut session).await;
+    tokio::spawn(expire_challenge(active_sessions, session.get_session_id()));
+    Ok(warp::reply::json(&SessionView::new(&session)))
+}
+
+//*
+// Armed when the challenge is made. If nobody answered by the time it expires the challenger is told
+// and the session removed, without waiting for the next clean up
+// */
+async fn expire_challenge(active_sessions: SessionList, session_id: SessionID) {
+    tokio::time::sleep(CHALLENGE_LIFETIME).await;
+    let mut active_sessions = active_sessions.write().await;
+    match active_sessions.get(&session_id) {
+        Some(session) => {
+            let mut session = session.write().await;
+            if !session.is_challenge_expired() {
+                return;
+            }
+            session.notify(SessionEvent::ChallengeExpired);
+        }
+        None => return
+    }
+    active_sessions.remove(&session_id);
+}
+
+//Challenges sent to the player that they haven't answered yet
+pub async fn handle_inbox(
+    active_sessions: SessionList, player: Player
+) -> Result<impl warp::Reply, warp::Rejection> {
+    session_controller::clean_up_session(active_sessions.clone()).await;
+
+    let mut result = Vec::new();
+    for session in active_sessions.read().await.values() {
+        let session = session.read().await;
+        if session.challenge.as_ref().is_some_and(|challenge| challenge.opponent == player.get_username()) {
+            result.push(SessionView::new(&session));
+        }
+    }
+    Ok(warp::reply::json(&result))
+}
+
+//The challenged player takes the second seat, the game starts
+pub async fn accept_challenge(
+    session_id: String, active_sessions: SessionList,
+    player: Player
+) -> Result<impl warp::Reply, warp::Rejection> {
+    match active_sessions.read().await.get(&SessionID(session_id)) {
+        Some(session) => {
+            let mut session = session.write().await;
+            if !is_challenged(&session, &player) || session.is_challenge_expired() {
+                return Err(warp::reject::custom(SessionNotExist));
+            }
+            session.challenge = None;
+            session.add_player2(player);
+            Ok(warp::reply::json(&SessionView::new(&session)))
+        }
+        None => Err(warp::reject::custom(SessionNotExist))
+    }
+}
+
+//*
+// Declined by the challenged player or taken back by the challenger, either way the session is removed
+// right away instead of waiting to expire
+// */
+pub async fn decline_challenge(
+    session_id: String, active_sessions: SessionList,
+    player: Player
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let session_id = SessionID(session_id);
+    let mut active_sessions = active_sessions.write().await;
+    {
+        let mut session = active_sessions.get(&session_id).ok_or(warp::reject::custom(SessionNotExist))?.write().await;
+        if session.challenge.is_none() {
+            return Err(warp::reject::custom(SessionNotExist));
+        }
+        if !is_challenged(&session, &player) && !session.is_creator(&player) {
+            return Err(warp::reject::custom(Error::Unauthorized));
+        }
+        session.notify(SessionEvent::ChallengeDeclined { username: player.get_username() });
+    }
+    active_sessions.remove(&session_id);
+    Ok(warp::reply::with_status("Challenge declined", warp::http::StatusCode::OK))
+}
+
+fn is_challenged(session: &Session<AnyGame>, player: &Player) -> bool {
+    session.challenge.as_ref().is_some_and(|challenge| challenge.opponent == player.get_username())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::controller::session_controller;
+    use crate::error::Error;
+    use crate::game::AnyGame;
+    use crate::game::xo::XO;
+    use crate::model::player::Player;
+    use crate::model::session::{Session, SessionList};
+    use super::accept_challenge;
+
+    fn player(username: &str) -> Player {
+        Player::new(username.to_string(), String::new())
+    }
+
+    //alice challenged bob, expires_at 0 is long gone
+    async fn challenge(active_sessions: &SessionList, expires_at: u64) -> String {
+        let mut session = Session::new(player("alice"), AnyGame::XO(XO::new()));
+        session.challenge("bob".to_string(), expires_at);
+        session_controller::insert_session(active_sessions, &mut session).await;
+        session.get_session_id().0
+    }
+
+    fn is_gone(result: Result<impl warp::Reply, warp::Rejection>) -> bool {
+        result.is_err_and(|rejection| matches!(rejection.find::<Error>(), Some(Error::SessionNotExist)))
+    }
+
+    #[tokio::test]
+    async fn expired_challenges_cannot_be_accepted_or_joined() {
+        let active_sessions = SessionList::default();
+        let session_id = challenge(&active_sessions, 0).await;
+        assert!(is_gone(accept_challenge(session_id.clone(), active_sessions.clone(), player("bob")).await));
+        assert!(is_gone(session_controller::join_session(session_id.clone(), active_sessions.clone(), player("bob")).await));
+        let sessions = active_sessions.read().await;
+        assert!(sessions.values().all(|session| session.try_read().unwrap().can_join()));
+    }
+
+    #[tokio::test]
+    async fn challenges_are_accepted_before_they_expire() {
+        let active_sessions = SessionList::default();
+        let session_id = challenge(&active_sessions, u64::MAX).await;
+        assert!(accept_challenge(session_id, active_sessions.clone(), player("bob")).await.is_ok());
+        let sessions = active_sessions.read().await;
+        assert!(sessions.values().all(|session| !session.try_read().unwrap().can_join()));
+    }
+}
@@ -0,0 +1,63 @@
+use std::convert::Infallible;
+use futures_util::{stream, StreamExt};
+use tokio::sync::broadcast::error::RecvError;
+use warp::sse::Event;
+use crate::error::Error::SessionNotExist;
+use crate::model::event::SessionEvent;
+use crate::model::session::{SessionID, SessionList};
+
+//*
+// Server-sent events of a session for spectators and clients without websocket. A client reconnecting
+// with the Last-Event-ID header first gets every event it missed
+// */
+pub async fn handle_events(
+    session_id: String, last_event_id: Option<u64>,
+    active_sessions: SessionList
+) -> Result<impl warp::Reply, warp::Rejection> {
+    //Read the log and subscribe under the same lock so no event is missed or sent twice
+    let session_id = SessionID(session_id);
+    let (missed, receiver) = match active_sessions.read().await.get(&session_id) {
+        Some(session) => {
+            let session = session.read().await;
+            (session.events_since(last_event_id.unwrap_or(0)), session.subscribe())
+        }
+        None => return Err(warp::reject::custom(SessionNotExist))
+    };
+
+    let last_sent = missed.last().map_or(last_event_id.unwrap_or(0), |(id, _)| *id);
+    let live = stream::unfold((receiver, last_sent), move |(mut receiver, last_sent)| {
+        let active_sessions = active_sessions.clone();
+        let session_id = session_id.clone();
+        async move {
+            loop {
+                match receiver.recv().await {
+                    Ok((id, _)) if id <= last_sent => continue,
+                    Ok(event) => return Some((vec![event.clone()], (receiver, event.0))),
+                    //Too slow to keep up with the channel, the events it dropped are still in the log
+                    Err(RecvError::Lagged(_)) => {
+                        let missed = missed_events(&active_sessions, &session_id, last_sent).await?;
+                        if let Some((id, _)) = missed.last() {
+                            let id = *id;
+                            return Some((missed, (receiver, id)));
+                        }
+                    }
+                    Err(RecvError::Closed) => return None
+                }
+            }
+        }
+    });
+    let events = stream::iter(missed)
+        .chain(live.flat_map(stream::iter))
+        .map(|(id, event)| Ok::<Event, Infallible>(Event::default().id(id.to_string()).json_data(event).unwrap()));
+
+    Ok(warp::sse::reply(warp::sse::keep_alive().stream(events)))
+}
+
+//Events of the session after the given one, None once the session is gone
+pub(crate) async fn missed_events(
+    active_sessions: &SessionList, session_id: &SessionID, last_event_id: u64
+) -> Option<Vec<(u64, SessionEvent)>> {
+    let active_sessions = active_sessions.read().await;
+    let session = active_sessions.get(session_id)?.read().await;
+    Some(session.events_since(last_event_id))
+}
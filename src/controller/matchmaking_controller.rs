@@ -0,0 +1,97 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use serde::Serialize;
+use tokio::sync::RwLock;
+use crate::controller::session_controller::{self, CreateSessionRequest};
+use crate::error::Error;
+use crate::error::Error::SessionNotExist;
+use crate::model::matchmaking::{Matchmaking, QueueEntry};
+use crate::model::player::Player;
+use crate::model::session::{Session, SessionList};
+use crate::model::view::SessionView;
+
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QueueStatus {
+    Queued,
+    Matched,
+}
+
+#[derive(Serialize)]
+pub struct QueueView {
+    status: QueueStatus,
+    //Starting from 1, while queued
+    position: Option<usize>,
+    //Both players are seated, the one who waited longer moves first
+    session: Option<SessionView>,
+}
+
+//*
+// Wait for an opponent asking for the same game. If someone already is, the session is made right away
+// with both players in it, otherwise wait for it on GET
+// */
+pub async fn enqueue(
+    active_sessions: SessionList,
+    matchmaking: Arc<RwLock<Matchmaking>>, player: Player, request: CreateSessionRequest
+) -> Result<impl warp::Reply, warp::Rejection> {
+    if request.against_computer() {
+        return Err(warp::reject::custom(Error::InvalidGameOption));
+    }
+    let game = request.new_game().map_err(warp::reject::custom)?;
+    let entry = QueueEntry::new(player.get_username(), game);
+
+    //Held until the session is made so the waiting player can't leave in between
+    let mut matchmaking = matchmaking.write().await;
+    let opponent = match matchmaking.enqueue(entry) {
+        Some(opponent) => opponent,
+        None => return Ok(warp::reply::json(&QueueView {
+            status: QueueStatus::Queued,
+            position: matchmaking.touch(&player.get_username()),
+            session: None,
+        }))
+    };
+    let mut session = Session::new(Player::new(opponent.username.clone(), String::new()), opponent.game);
+    session.private = true;
+    session.add_player2(player);
+    session_controller::insert_session(&active_sessions, &mut session).await;
+    matchmaking.add_match(opponent.username, session.get_session_id());
+    Ok(warp::reply::json(&QueueView { status: QueueStatus::Matched, position: None, session: Some(SessionView::new(&session)) }))
+}
+
+//*
+// Long poll the queue, answers as soon as the player is paired or after ?timeout= seconds (at most
+// MAX_WAIT_SECONDS, 0 by default) with their position. Polling keeps the player in the queue
+// */
+pub async fn handle_wait_for_match(
+    active_sessions: SessionList,
+    matchmaking: Arc<RwLock<Matchmaking>>, params: HashMap<String, String>, player: Player
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let changed = matchmaking.read().await.watch();
+    let (active_sessions, matchmaking, player) = (&active_sessions, &matchmaking, &player);
+    session_controller::long_poll(changed, &params, |timed_out| async move {
+        let mut matchmaking = matchmaking.write().await;
+        if let Some(session_id) = matchmaking.take_match(&player.get_username()) {
+            //Not holding the queue while reading the session
+            drop(matchmaking);
+            return match active_sessions.read().await.get(&session_id) {
+                Some(session) => Ok(Some(warp::reply::json(&QueueView {
+                    status: QueueStatus::Matched,
+                    position: None,
+                    session: Some(SessionView::new(&*session.read().await)),
+                }))),
+                None => Err(warp::reject::custom(SessionNotExist))
+            };
+        }
+        let position = matchmaking.touch(&player.get_username()).ok_or(warp::reject::custom(Error::NotQueued))?;
+        Ok(timed_out.then(|| warp::reply::json(&QueueView { status: QueueStatus::Queued, position: Some(position), session: None })))
+    }).await
+}
+
+pub async fn leave_queue(
+    matchmaking: Arc<RwLock<Matchmaking>>, player: Player
+) -> Result<impl warp::Reply, warp::Rejection> {
+    if !matchmaking.write().await.leave(&player.get_username()) {
+        return Err(warp::reject::custom(Error::NotQueued));
+    }
+    Ok(warp::reply::with_status("Left the queue", warp::http::StatusCode::OK))
+}
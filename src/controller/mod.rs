@@ -1,3 +1,8 @@
+pub mod account_controller;
 pub mod authentication_controller;
-pub mod multithread_session_controller;
-pub mod session_controller;
\ No newline at end of file
+pub mod challenge_controller;
+pub mod event_controller;
+pub mod matchmaking_controller;
+pub mod openapi_controller;
+pub mod session_controller;
+pub mod socket_controller;
\ No newline at end of file
@@ -0,0 +1,667 @@
+use serde_json::{json, Value};
+
+//*
+// OpenAPI 3 document of the /v1 routes, client teams generate their code from it.
+// Keep it in sync with the v1 filters in main
+// */
+pub async fn handle_openapi() -> Result<impl warp::Reply, warp::Rejection> {
+    Ok(warp::reply::json(&spec()))
+}
+
+fn spec() -> Value {
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "xogamedev",
+            "version": "1",
+            "description": "XO, Gomoku, Connect Four and Ultimate XO against other players or the computer"
+        },
+        "servers": [{ "url": "/xogamedev/v1" }],
+        "paths": paths(),
+        "components": {
+            "securitySchemes": {
+                "bearerAuth": { "type": "http", "scheme": "bearer" }
+            },
+            "schemas": schemas()
+        }
+    })
+}
+
+fn paths() -> Value {
+    json!({
+        "/players": {
+            "post": validated(operation("register", "Create an account", Some("Credentials"), None))
+        },
+        "/players/login": {
+            "post": rate_limited(operation("login", "Get a bearer token for the session routes", Some("Credentials"), Some(schema("Token"))))
+        },
+        "/players/logout": {
+            "post": secured(operation("logout", "Revoke the bearer token of the request", None, None))
+        },
+        "/players/guest": {
+            "post": rate_limited(operation("guestLogin", "Quick play as a guest with a generated name, no password to log in again",
+                None, Some(schema("GuestToken"))))
+        },
+        "/players/me/upgrade": {
+            "post": validated(secured(operation("upgradeGuest",
+                "Turn the guest account into a registered one, its games follow it. 409 if it is not a guest",
+                Some("Credentials"), Some(schema("Renamed")))))
+        },
+        "/players/me": {
+            "delete": rate_limited(secured(operation("deleteAccount", "Delete the account, its games stay on the scoreboard as [deleted]",
+                Some("DeleteAccount"), None)))
+        },
+        "/players/me/password": {
+            "put": rate_limited(validated(secured(operation("changePassword", "Change the password and revoke every token of the player",
+                Some("ChangePassword"), Some(schema("Token"))))))
+        },
+        "/players/me/username": {
+            "put": validated(secured(operation("rename", "Change the username, the player id and the games stay the same. Guests upgrade their account instead",
+                Some("Rename"), Some(schema("Renamed")))))
+        },
+        "/sessions": {
+            "get": operation("listSessions", "Sessions waiting for a second player", None, Some(array("SessionView"))),
+            "post": secured(operation("createSession", "Start a session, against the computer if a difficulty is given",
+                Some("CreateSession"), Some(schema("SessionView"))))
+        },
+        "/sessions/{sessionId}": {
+            "parameters": [session_id()],
+            "get": operation("getSession", "Current state of a session", None, Some(schema("SessionView")))
+        },
+        "/codes/{joinCode}": {
+            "parameters": [join_code()],
+            "get": operation("getSessionByCode", "Current state of the session with this join code", None, Some(schema("SessionView")))
+        },
+        "/codes/{joinCode}/players": {
+            "parameters": [join_code()],
+            "post": secured(operation("joinSessionByCode", "Take the second seat of the session with this join code",
+                None, Some(schema("SessionView"))))
+        },
+        "/sessions/{sessionId}/code": {
+            "parameters": [session_id()],
+            "post": secured(operation("regenerateJoinCode", "New join code for the session, creator only. The old code stops working",
+                None, Some(schema("SessionView")))),
+            "delete": secured(operation("revokeJoinCode", "Remove the join code, creator only. Invited players can still join by id",
+                None, Some(schema("SessionView"))))
+        },
+        "/challenges": {
+            "get": secured(operation("challengeInbox", "Challenges sent to you that you haven't answered", None, Some(array("SessionView")))),
+            "post": secured(operation("createChallenge",
+                "Session only the opponent can join, removed if they decline or don't accept in 10 minutes. 404 if there is no such player",
+                Some("ChallengeRequest"), Some(schema("SessionView"))))
+        },
+        "/challenges/{sessionId}/accept": {
+            "parameters": [session_id()],
+            "post": secured(operation("acceptChallenge", "Take the second seat of a challenge sent to you", None, Some(schema("SessionView"))))
+        },
+        "/challenges/{sessionId}/decline": {
+            "parameters": [session_id()],
+            "post": secured(operation("declineChallenge", "Decline a challenge sent to you or take back one you sent", None, None))
+        },
+        "/matchmaking": {
+            "post": secured(operation("enqueue",
+                "Wait for an opponent asking for the same game, matched right away if someone already is",
+                Some("QueueRequest"), Some(schema("QueueView")))),
+            "get": with_parameters(secured(operation("waitForMatch",
+                "Long poll the queue until you are matched, polling keeps you in the queue. 404 if you are not in it",
+                None, Some(schema("QueueView")))), json!([{
+                    "name": "timeout",
+                    "in": "query",
+                    "required": false,
+                    "description": "Seconds to wait for a match, at most 60",
+                    "schema": { "type": "integer", "minimum": 0, "maximum": 60 }
+                }])),
+            "delete": secured(operation("leaveQueue", "Stop waiting for an opponent", None, None))
+        },
+        "/sessions/{sessionId}/players": {
+            "parameters": [session_id()],
+            "post": secured(operation("joinSession", "Take the second seat of a session, 403 for a private session you aren't invited to",
+                None, Some(schema("SessionView"))))
+        },
+        "/sessions/{sessionId}/moves": {
+            "parameters": [session_id()],
+            "post": secured(operation("makeMove", "Play a move, the computer replies in the same request",
+                Some("MoveRequest"), Some(schema("SessionView"))))
+        },
+        "/sessions/{sessionId}/turn": {
+            "parameters": [
+                session_id(),
+                {
+                    "name": "timeout",
+                    "in": "query",
+                    "required": false,
+                    "description": "Seconds to wait for the turn of the player or the end of the game, at most 60",
+                    "schema": { "type": "integer", "minimum": 0, "maximum": 60 }
+                }
+            ],
+            "post": secured(operation("waitForTurn", "Whether it is the turn of the player", None, Some(schema("TurnView"))))
+        },
+        "/sessions/{sessionId}/surrender": {
+            "parameters": [session_id()],
+            "post": secured(operation("surrender", "Give up, the opponent wins", None, Some(schema("SessionView"))))
+        },
+        "/sessions/{sessionId}/takeback": {
+            "parameters": [session_id()],
+            "post": secured(operation("requestTakeback", "Ask the opponent to take back the last move of the player",
+                None, Some(schema("SessionView"))))
+        },
+        "/sessions/{sessionId}/takeback/{answer}": {
+            "parameters": [
+                session_id(),
+                {
+                    "name": "answer",
+                    "in": "path",
+                    "required": true,
+                    "schema": { "type": "string", "enum": ["accept", "decline"] }
+                }
+            ],
+            "post": secured(operation("answerTakeback", "Accept or decline the takeback asked by the opponent",
+                None, Some(schema("SessionView"))))
+        },
+        "/sessions/{sessionId}/analysis": {
+            "parameters": [session_id()],
+            "get": with_parameters(secured(operation("analyzeSession",
+                "Score of every legal move for the player to move. Only the players of a game still being played can analyze it, and games between two people only once they are over",
+                None, Some(schema("Analysis")))), json!([{
+                    "name": "ply",
+                    "in": "query",
+                    "required": false,
+                    "description": "Analyze the position after this many moves, default to the current position",
+                    "schema": { "type": "integer", "minimum": 0 }
+                }]))
+        },
+        "/sessions/{sessionId}/events": {
+            "parameters": [
+                session_id(),
+                {
+                    "name": "Last-Event-ID",
+                    "in": "header",
+                    "required": false,
+                    "description": "Id of the last event received, the stream starts after it",
+                    "schema": { "type": "integer" }
+                }
+            ],
+            "get": {
+                "operationId": "sessionEvents",
+                "summary": "Server-sent events of the session, each data is a SessionEvent",
+                "responses": {
+                    "200": {
+                        "description": "Event stream",
+                        "content": { "text/event-stream": { "schema": schema("SessionEvent") } }
+                    },
+                    "default": error_response()
+                }
+            }
+        },
+        "/sessions/{sessionId}/ws": {
+            "parameters": [
+                session_id(),
+                {
+                    "name": "token",
+                    "in": "query",
+                    "required": true,
+                    "description": "Bearer token, browsers can't set headers on a websocket",
+                    "schema": { "type": "string" }
+                }
+            ],
+            "get": {
+                "operationId": "sessionSocket",
+                "summary": "Websocket sending every SessionEvent, send {\"move\": \"5\"} to play",
+                "responses": {
+                    "101": { "description": "Switching to the websocket protocol" },
+                    "default": error_response()
+                }
+            }
+        },
+        "/replays/{savedSessionId}": {
+            "parameters": [{
+                "name": "savedSessionId",
+                "in": "path",
+                "required": true,
+                "schema": { "type": "integer" }
+            }],
+            "get": operation("getReplay", "Moves of a finished session with the board after each of them", None, Some(schema("Replay")))
+        },
+        "/scoreboard": {
+            "parameters": [{
+                "name": "guests",
+                "in": "query",
+                "required": false,
+                "description": "true to include sessions with a guest in them",
+                "schema": { "type": "boolean", "default": false }
+            }],
+            "get": operation("getScoreboard", "Last finished sessions", None, Some(array("ScoreboardEntry")))
+        },
+        "/openapi.json": {
+            "get": operation("getOpenApi", "This document", None, Some(json!({ "type": "object" })))
+        }
+    })
+}
+
+//Split in two, one json! with all of them goes past the macro recursion limit
+fn schemas() -> Value {
+    let mut schemas = account_schemas();
+    if let (Some(schemas), Value::Object(session_schemas)) = (schemas.as_object_mut(), session_schemas()) {
+        schemas.extend(session_schemas);
+    }
+    schemas
+}
+
+fn account_schemas() -> Value {
+    json!({
+        "Credentials": {
+            "type": "object",
+            "required": ["username", "password"],
+            "properties": {
+                "username": { "type": "string" },
+                "password": { "type": "string" }
+            }
+        },
+        "Token": {
+            "type": "object",
+            "properties": {
+                "token": { "type": "string" },
+                "token_type": { "type": "string", "enum": ["Bearer"] },
+                "expires_at": { "type": "integer", "description": "Seconds since epoch" }
+            }
+        },
+        "GuestToken": {
+            "allOf": [
+                schema("Token"),
+                {
+                    "type": "object",
+                    "properties": { "username": { "type": "string" } }
+                }
+            ]
+        },
+        "ValidationError": {
+            "type": "object",
+            "properties": {
+                "reason": {
+                    "type": "string",
+                    "enum": [
+                        "username_too_short", "username_too_long", "username_invalid_characters", "username_reserved",
+                        "username_taken", "password_too_short", "password_too_long", "password_too_weak"
+                    ]
+                },
+                "message": { "type": "string" }
+            }
+        },
+        "ChangePassword": {
+            "type": "object",
+            "required": ["current_password", "new_password"],
+            "properties": {
+                "current_password": { "type": "string" },
+                "new_password": { "type": "string" }
+            }
+        },
+        "Rename": {
+            "type": "object",
+            "required": ["username"],
+            "properties": { "username": { "type": "string" } }
+        },
+        "Renamed": {
+            "allOf": [
+                schema("Token"),
+                {
+                    "type": "object",
+                    "properties": {
+                        "player_id": { "type": "integer" },
+                        "username": { "type": "string" }
+                    }
+                }
+            ]
+        },
+        "DeleteAccount": {
+            "type": "object",
+            "required": ["password"],
+            "properties": { "password": { "type": "string" } }
+        }
+    })
+}
+
+fn session_schemas() -> Value {
+    json!({
+        "CreateSession": {
+            "type": "object",
+            "properties": {
+                "game": { "type": "string", "enum": ["XO", "Gomoku", "ConnectFour", "UltimateXO"], "default": "XO" },
+                "rows": { "type": "integer", "description": "XO and Gomoku only" },
+                "cols": { "type": "integer", "description": "XO and Gomoku only" },
+                "k": { "type": "integer", "description": "Marks in a row to win, from 3 to the shorter side of the board. XO and Gomoku only" },
+                "computer": { "type": "boolean", "default": false },
+                "difficulty": schema("Difficulty"),
+                "private": { "type": "boolean", "default": false, "description": "Hidden from the session list" },
+                "invited": {
+                    "type": "array",
+                    "items": { "type": "string" },
+                    "description": "Usernames that can join by session id, makes the session private"
+                }
+            }
+        },
+        "MoveRequest": {
+            "type": "object",
+            "required": ["move"],
+            "properties": {
+                "move": {
+                    "type": "string",
+                    "description": "Cell from 1 for XO, column from 1 for Connect Four, board * 10 + cell for Ultimate XO"
+                }
+            }
+        },
+        "Difficulty": { "type": "string", "enum": ["random", "greedy", "shallow", "perfect"] },
+        "SessionStatus": { "type": "string", "enum": ["waiting_for_opponent", "in_progress", "won", "draw"] },
+        "Board": {
+            "type": "array",
+            "description": "Cells row by row, \" \" for an empty cell",
+            "items": { "type": "array", "items": { "type": "string" } }
+        },
+        "Move": {
+            "type": "object",
+            "properties": {
+                "player": player_index(),
+                "player_input": { "type": "integer" },
+                "played_at": { "type": "integer", "description": "Milliseconds since epoch" }
+            }
+        },
+        "SessionView": {
+            "type": "object",
+            "properties": {
+                "session_id": { "type": "string" },
+                "join_code": {
+                    "type": "string",
+                    "nullable": true,
+                    "description": "Short case insensitive code to give to the opponent, null for saved sessions. Only the creator sees the code of a private session"
+                },
+                "private": { "type": "boolean" },
+                "challenge": nullable("Challenge"),
+                "game": { "type": "string", "description": "XO, Gomoku, ConnectFour, UltimateXO or \"XO rows,cols,k\" for other board sizes" },
+                "players": {
+                    "type": "array",
+                    "minItems": 2,
+                    "maxItems": 2,
+                    "items": { "type": "string", "nullable": true }
+                },
+                "bot": nullable("Difficulty"),
+                "board": schema("Board"),
+                "meta_board": {
+                    "allOf": [schema("Board")],
+                    "nullable": true,
+                    "description": "UltimateXO only, winner of each small board and \"-\" for a draw"
+                },
+                "next_board": {
+                    "type": "integer",
+                    "minimum": 1,
+                    "maximum": 9,
+                    "nullable": true,
+                    "description": "UltimateXO only, small board the next move goes in, null when it can go in any"
+                },
+                "turn": nullable_index(),
+                "status": schema("SessionStatus"),
+                "winner": nullable_index(),
+                "moves": array("Move"),
+                "takeback_request": nullable_index()
+            }
+        },
+        "Challenge": {
+            "type": "object",
+            "properties": {
+                "opponent": { "type": "string" },
+                "expires_at": { "type": "integer", "description": "Seconds since epoch" }
+            }
+        },
+        "ChallengeRequest": {
+            "allOf": [
+                schema("CreateSession"),
+                {
+                    "type": "object",
+                    "required": ["opponent"],
+                    "properties": { "opponent": { "type": "string", "description": "Username of the challenged player" } }
+                }
+            ],
+            "description": "computer, difficulty, private and invited are not used, a challenge is always private to the opponent"
+        },
+        "QueueRequest": {
+            "allOf": [schema("CreateSession")],
+            "description": "computer, difficulty, private and invited are not used, players are matched with players asking for the same game and options"
+        },
+        "QueueView": {
+            "type": "object",
+            "properties": {
+                "status": { "type": "string", "enum": ["queued", "matched"] },
+                "position": { "type": "integer", "nullable": true, "description": "From 1, while queued" },
+                "session": nullable("SessionView")
+            }
+        },
+        "TurnView": {
+            "type": "object",
+            "properties": {
+                "your_turn": { "type": "boolean" },
+                "session": schema("SessionView")
+            }
+        },
+        "SessionEvent": {
+            "type": "object",
+            "required": ["event"],
+            "properties": {
+                "event": {
+                    "type": "string",
+                    "enum": [
+                        "opponent_joined", "move_made", "takeback_requested", "takeback_declined", "taken_back", "surrender", "game_ended",
+                        "challenge_declined", "challenge_expired"
+                    ]
+                },
+                "username": { "type": "string" },
+                "player": player_index(),
+                "player_input": { "type": "integer" },
+                "board": schema("Board"),
+                "status": schema("SessionStatus"),
+                "winner": nullable_index()
+            }
+        },
+        "Analysis": {
+            "type": "object",
+            "properties": {
+                "prediction": schema("Prediction"),
+                "best_moves": { "type": "array", "items": { "type": "integer" } },
+                "scores": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "player_input": { "type": "integer" },
+                            "score": { "type": "integer" },
+                            "prediction": schema("Prediction")
+                        }
+                    }
+                },
+                "depth": { "type": "integer", "nullable": true, "description": "null when searched until the end of the game" }
+            }
+        },
+        "Prediction": { "type": "string", "enum": ["win", "loss", "draw", "unknown"] },
+        "Replay": {
+            "type": "object",
+            "properties": {
+                "session_id": { "type": "string" },
+                "game": { "type": "string", "description": "XO, Gomoku, ConnectFour, UltimateXO or \"XO rows,cols,k\" for other board sizes" },
+                "players": { "type": "array", "items": { "type": "string" } },
+                "status": schema("SessionStatus"),
+                "winner": nullable_index(),
+                "plies": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "ply": { "type": "integer" },
+                            "player": player_index(),
+                            "player_input": { "type": "integer" },
+                            "played_at": { "type": "integer" },
+                            "board": schema("Board")
+                        }
+                    }
+                }
+            }
+        },
+        "ScoreboardEntry": {
+            "allOf": [
+                schema("SessionView"),
+                {
+                    "type": "object",
+                    "properties": { "replay": { "type": "string", "description": "Path of the replay" } }
+                }
+            ]
+        }
+    })
+}
+
+fn operation(id: &str, summary: &str, body: Option<&str>, response: Option<Value>) -> Value {
+    let mut operation = json!({
+        "operationId": id,
+        "summary": summary,
+        "responses": {
+            "200": match response {
+                Some(response) => json!({
+                    "description": "OK",
+                    "content": { "application/json": { "schema": response } }
+                }),
+                None => json!({
+                    "description": "OK",
+                    "content": { "text/plain": { "schema": { "type": "string" } } }
+                })
+            },
+            "default": error_response()
+        }
+    });
+    if let Some(body) = body {
+        operation["requestBody"] = json!({
+            "required": true,
+            "content": { "application/json": { "schema": schema(body) } }
+        });
+    }
+    operation
+}
+
+//Needs the token from login in the Authorization header
+//Parameters of this operation only, not of every operation on the path
+fn with_parameters(mut operation: Value, parameters: Value) -> Value {
+    operation["parameters"] = parameters;
+    operation
+}
+
+fn secured(mut operation: Value) -> Value {
+    operation["security"] = json!([{ "bearerAuth": [] }]);
+    operation
+}
+
+//Username or password refused, the reason says which rule
+fn validated(mut operation: Value) -> Value {
+    operation["responses"]["422"] = json!({
+        "description": "Invalid username or password",
+        "content": { "application/json": { "schema": schema("ValidationError") } }
+    });
+    operation
+}
+
+fn rate_limited(mut operation: Value) -> Value {
+    operation["responses"]["429"] = json!({
+        "description": "Too many attempts from this address or for this username, wait for Retry-After seconds",
+        "headers": { "Retry-After": { "schema": { "type": "integer" } } }
+    });
+    operation
+}
+
+//Errors are plain text, see handle_error in main for the status codes
+fn error_response() -> Value {
+    json!({
+        "description": "Error message",
+        "content": { "text/plain": { "schema": { "type": "string" } } }
+    })
+}
+
+fn session_id() -> Value {
+    json!({ "name": "sessionId", "in": "path", "required": true, "schema": { "type": "string" } })
+}
+
+fn join_code() -> Value {
+    json!({ "name": "joinCode", "in": "path", "required": true, "schema": { "type": "string" } })
+}
+
+fn schema(name: &str) -> Value {
+    json!({ "$ref": format!("#/components/schemas/{}", name) })
+}
+
+fn array(name: &str) -> Value {
+    json!({ "type": "array", "items": schema(name) })
+}
+
+fn nullable(name: &str) -> Value {
+    json!({ "allOf": [schema(name)], "nullable": true })
+}
+
+//Players are referred to by their index in SessionView.players
+fn player_index() -> Value {
+    json!({ "type": "integer", "minimum": 0, "maximum": 1 })
+}
+
+fn nullable_index() -> Value {
+    json!({ "type": "integer", "minimum": 0, "maximum": 1, "nullable": true })
+}
+
+#[cfg(test)]
+mod tests {
+    use warp::http::StatusCode;
+    use crate::auth::TokenService;
+    use crate::dao::DAO;
+    use crate::dao::postgres::PostgresDB;
+    use super::spec;
+
+    //Method and path of every documented route, parameters filled in with 1
+    fn documented_routes() -> Vec<(String, String)> {
+        let spec = spec();
+        let mut routes = Vec::new();
+        for (path, item) in spec["paths"].as_object().unwrap() {
+            let path = path.split('/')
+                .map(|segment| if segment.starts_with('{') { "1" } else { segment })
+                .collect::<Vec<_>>()
+                .join("/");
+            for method in ["get", "post", "put", "delete"] {
+                if item.get(method).is_some() {
+                    routes.push((method.to_uppercase(), path.clone()));
+                }
+            }
+        }
+        routes
+    }
+
+    //*
+    // Nobody is logged in and there is no database, so the requests are refused. A route that exists
+    // refuses them for another reason than 404 or 405
+    // */
+    async fn is_routed(method: &str, path: &str) -> bool {
+        let dao = DAO::new(PostgresDB::lazy("postgres://postgres@127.0.0.1:1/xogamedev"));
+        let tokens = TokenService::new(b"test key".to_vec(), TokenService::DEFAULT_LIFETIME);
+        let status = warp::test::request()
+            .method(method)
+            .path(&format!("/xogamedev/v1{}", path))
+            .reply(&crate::routes(dao, tokens))
+            .await
+            .status();
+        status != StatusCode::NOT_FOUND && status != StatusCode::METHOD_NOT_ALLOWED
+    }
+
+    #[tokio::test]
+    async fn every_documented_route_is_served() {
+        let routes = documented_routes();
+        assert!(!routes.is_empty());
+        for (method, path) in routes {
+            assert!(is_routed(&method, &path).await, "{} {} is documented but not routed", method, path);
+        }
+    }
+
+    #[tokio::test]
+    async fn undocumented_routes_are_not_served() {
+        assert!(!is_routed("GET", "/nothing").await);
+        assert!(!is_routed("DELETE", "/scoreboard").await);
+        assert!(!is_routed("GET", "/sessions/1/nothing").await);
+    }
+}
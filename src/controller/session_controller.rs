@@ -1,59 +1,229 @@
 use std::collections::HashMap;
-use std::ops::{Deref, DerefMut};
+use std::future::Future;
 use std::sync::Arc;
-use tokio::sync::RwLock;
-use warp::http::StatusCode;
-use warp::path::param;
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Notify;
+use tokio::time::Instant;
+use crate::ai::{analysis, Bot, Difficulty};
 use crate::dao::{DAO, Database};
 use crate::error::Error;
 use crate::error::Error::{DatabaseError, SessionNotExist};
-use crate::game::Game;
+use crate::game::{AnyGame, Game, MoveError};
+use crate::game::connect_four::ConnectFour;
+use crate::game::ultimate_xo::UltimateXO;
 use crate::game::xo::XO;
-use crate::model::session::{Session, SessionID};
+use crate::model::event::SessionEvent;
+use crate::model::session::{Session, SessionID, SessionList};
 use crate::model::player::Player;
+use crate::model::view::{SessionStatus, SessionView};
 
-pub async fn create_session(active_session: Arc<RwLock<HashMap<SessionID,
-    RwLock<Session<XO>>>>>, player: Player, game: String) -> Result<impl warp::Reply, warp::Rejection> {
-    let game = match game.as_str() {
-        "XO" => XO::new(),
-        _ => XO::new()
-    };
-    let session = Session::new(player, game);
-    active_session.write().await.insert(session.get_session_id().clone(), RwLock::new(session.clone()));
-    Ok(warp::reply::with_status(session.get_session_id().0, StatusCode::OK))
+//Longest a long poll request can be held open
+const MAX_WAIT_SECONDS: u64 = 60;
+
+#[derive(Deserialize)]
+pub struct CreateSessionRequest {
+    #[serde(default = "CreateSessionRequest::default_game")]
+    game: String,
+    //Board size for m,n,k games, default to the preset of the chosen game
+    rows: Option<usize>,
+    cols: Option<usize>,
+    k: Option<usize>,
+    //Play against the computer instead of waiting for another player
+    #[serde(default)]
+    computer: bool,
+    //Setting a difficulty also means playing against the computer, default to perfect
+    difficulty: Option<Difficulty>,
+    //Hidden from the lobby, joined with the join code or by the invited usernames
+    #[serde(default)]
+    private: bool,
+    //Inviting someone also makes the session private
+    #[serde(default)]
+    invited: Vec<String>,
+}
+
+impl CreateSessionRequest {
+    fn default_game() -> String { "XO".to_string() }
+
+    pub fn against_computer(&self) -> bool {
+        self.computer || self.difficulty.is_some()
+    }
+
+    pub fn new_game(&self) -> Result<AnyGame, Error> {
+        let preset = match self.game.as_str() {
+            "XO" => XO::new(),
+            "Gomoku" => XO::gomoku(),
+            "ConnectFour" => return Ok(AnyGame::ConnectFour(ConnectFour::new())),
+            "UltimateXO" => return Ok(AnyGame::UltimateXO(UltimateXO::new())),
+            _ => return Err(Error::InvalidGameOption)
+        };
+        if self.rows.is_none() && self.cols.is_none() && self.k.is_none() {
+            return Ok(AnyGame::XO(preset));
+        }
+
+        let (rows, cols, k) = preset.size();
+        let rows = self.rows.unwrap_or(rows);
+        let cols = self.cols.unwrap_or(cols);
+        let k = self.k.unwrap_or(k);
+        if !XO::is_valid_size(rows, cols, k) {
+            return Err(Error::InvalidGameOption);
+        }
+        Ok(AnyGame::XO(XO::with_size(rows, cols, k)))
+    }
+}
+
+pub async fn create_session(active_session: SessionList, player: Player, request: CreateSessionRequest) -> Result<impl warp::Reply, warp::Rejection> {
+    let game = request.new_game().map_err(warp::reject::custom)?;
+    let mut session = Session::new(player, game);
+    if request.against_computer() {
+        let difficulty = request.difficulty.unwrap_or(Difficulty::Perfect);
+        session.add_bot(Bot::new(difficulty));
+    }
+    session.private = request.private || !request.invited.is_empty();
+    session.invited = request.invited;
+    insert_session(&active_session, &mut session).await;
+    Ok(warp::reply::json(&SessionView::for_creator(&session)))
+}
+
+//*
+// Add a new session to the active ones, with new ids if the ones it has are taken. The write lock is held
+// from the check to the insert so no other session can take the same ids in between
+// */
+pub async fn insert_session(
+    active_sessions: &SessionList, session: &mut Session<AnyGame>
+) {
+    active_sessions.write().await.insert(session);
+}
+
+//*
+// New join code for the session, the old one stops working. Only the creator can change it, for a
+// private session it's the way to take back an invite code that was shared too widely
+// */
+pub async fn regenerate_join_code(
+    session_id: String, active_sessions: SessionList, player: Player
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let session_id = SessionID(session_id);
+    //Write lock on the list so no other session takes the new code before it is set
+    let mut active_sessions = active_sessions.write().await;
+    let session = active_sessions.get(&session_id).ok_or(warp::reject::custom(SessionNotExist))?;
+    if !session.read().await.is_creator(&player) {
+        return Err(warp::reject::custom(Error::Unauthorized));
+    }
+    let code = active_sessions.unused_join_code();
+    active_sessions.set_join_code(&session_id, Some(code));
+    let session = active_sessions.get(&session_id).ok_or(warp::reject::custom(SessionNotExist))?.read().await;
+    Ok(warp::reply::json(&SessionView::for_creator(&session)))
+}
+
+//*
+// No join code at all, invited players can still join a private session by its id
+// */
+pub async fn revoke_join_code(
+    session_id: String, active_sessions: SessionList, player: Player
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let session_id = SessionID(session_id);
+    let mut active_sessions = active_sessions.write().await;
+    let session = active_sessions.get(&session_id).ok_or(warp::reject::custom(SessionNotExist))?;
+    if !session.read().await.is_creator(&player) {
+        return Err(warp::reject::custom(Error::Unauthorized));
+    }
+    active_sessions.set_join_code(&session_id, None);
+    let session = active_sessions.get(&session_id).ok_or(warp::reject::custom(SessionNotExist))?.read().await;
+    Ok(warp::reply::json(&SessionView::for_creator(&session)))
+}
+
+//Session id of the active session with this join code
+async fn find_by_join_code(
+    active_sessions: &SessionList, code: &str
+) -> Option<String> {
+    active_sessions.read().await.find_by_join_code(code).map(|session_id| session_id.0.clone())
+}
+
+//Same as handle_session with the join code in place of the session id
+pub async fn handle_join_code(
+    code: String, active_sessions: SessionList
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let session_id = find_by_join_code(&active_sessions, &code).await
+        .ok_or(warp::reject::custom(SessionNotExist))?;
+    handle_session(session_id, active_sessions).await
+}
+
+//Same as join_session with the join code in place of the session id
+pub async fn join_by_code(
+    code: String, active_sessions: SessionList, player2: Player
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let session_id = find_by_join_code(&active_sessions, &code).await
+        .ok_or(warp::reject::custom(SessionNotExist))?;
+    //Knowing the code is the invitation
+    join(session_id, active_sessions, player2, true).await
 }
 
 //Return joinable session
-pub async fn get_session(active_sessions: Arc<RwLock<HashMap<SessionID,
-    RwLock<Session<impl Game + Clone>>>>>) -> Result<impl warp::Reply, warp::Rejection> {
+pub async fn get_session(active_sessions: SessionList) -> Result<impl warp::Reply, warp::Rejection> {
     clean_up_session(active_sessions.clone()).await;
 
-    let mut result: Vec<(SessionID, String)> = Vec::new();
-    for (session_id, session) in active_sessions.read().await.iter() {
-        result.push((session_id.clone(), session.read().await.players[0].clone().unwrap().get_username()));
+    let mut result = Vec::new();
+    for session in active_sessions.read().await.values() {
+        let session = session.read().await;
+        if session.can_join() && !session.private {
+            result.push(SessionView::new(&session));
+        }
+    }
+    Ok(warp::reply::json(&result))
+}
+
+//One session, finished sessions are only kept until the next clean up
+pub async fn handle_session(
+    session_id: String, active_sessions: SessionList
+) -> Result<impl warp::Reply, warp::Rejection> {
+    match active_sessions.read().await.get(&SessionID(session_id)) {
+        Some(session) => Ok(warp::reply::json(&SessionView::new(&*session.read().await))),
+        None => Err(warp::reject::custom(SessionNotExist))
     }
-    return Ok(warp::reply::json(&result));
 }
 
 pub async fn join_session(
-    session_id: String, active_sessions: Arc<RwLock<HashMap<SessionID, RwLock<Session<impl Game + Clone>>>>>,
+    session_id: String, active_sessions: SessionList,
     player2: Player) -> Result<impl warp::Reply, warp::Rejection> {
+    join(session_id, active_sessions, player2, false).await
+}
+
+async fn join(
+    session_id: String, active_sessions: SessionList,
+    player2: Player, with_code: bool) -> Result<warp::reply::Json, warp::Rejection> {
     match active_sessions.read().await.get(&SessionID(session_id.clone())) {
         Some(session) => {
             let mut session = session.write().await;
-            session.add_player2(player2)
+            if !session.can_join() {
+                return Err(warp::reject::custom(Error::SessionFull));
+            }
+            if !with_code && !session.is_invited(&player2) {
+                return Err(warp::reject::custom(Error::NotInvited));
+            }
+            //Same as an expired challenge that is already cleaned up
+            if session.is_challenge_expired() {
+                return Err(warp::reject::custom(SessionNotExist));
+            }
+            //Joining a challenge by id is the same as accepting it
+            session.challenge = None;
+            session.add_player2(player2);
+            Ok(warp::reply::json(&SessionView::new(&session)))
         },
-        None => { return Err(warp::reject::custom(SessionNotExist)); }
+        None => Err(warp::reject::custom(SessionNotExist))
     }
-    Ok(warp::reply::with_status(session_id.clone(), StatusCode::OK))
 }
 
 pub async fn clean_up_session(
-    mut active_sessions: Arc<RwLock<HashMap<SessionID, RwLock<Session<impl Game + Clone>>>>>
+    active_sessions: SessionList
 ) {
     let mut ended_session_list = Vec::new();
     for (session_id, session) in active_sessions.read().await.iter() {
-        if session.read().await.end {
+        let mut session = session.write().await;
+        if session.is_challenge_expired() {
+            //Tell the challenger before the session is gone
+            session.notify(SessionEvent::ChallengeExpired);
+            ended_session_list.push(session_id.clone());
+        } else if session.end {
             ended_session_list.push(session_id.clone());
         }
     }
@@ -64,139 +234,407 @@ pub async fn clean_up_session(
 
 pub async fn handle_make_a_move(
     session_id: String,
-    active_sessions: Arc<RwLock<HashMap<SessionID, RwLock<Session<XO>>>>>,
-    params: HashMap<String, String>, dao: DAO<impl Database>
+    active_sessions: SessionList,
+    player: Player, params: HashMap<String, String>, dao: DAO<impl Database>
 ) -> Result<impl warp::Reply, warp::Rejection> {
     println!("handle make a move");
-    if let None = active_sessions.read().await.get(&SessionID(session_id.clone())) {
-        return Err(warp::reject::custom(SessionNotExist));
+    match params.get("move") {
+        Some(value) => {
+            let session = play_turn(&SessionID(session_id), &active_sessions, player, value, dao).await
+                .map_err(warp::reject::custom)?;
+            Ok(warp::reply::json(&session))
+        }
+        None => Err(warp::reject::custom(Error::InvalidMove(MoveError::OutOfRange)))
     }
-    let session = active_sessions.read().await;
-    let session = session.get(&SessionID(session_id)).unwrap();
-    let mut session = session.write().await;
+}
 
-    let mut player = Player::new(
-        params.get("username").unwrap_or(&"".to_string()).clone(),
-        params.get("password").unwrap_or(&"".to_string()).clone()
-    );
-    player.set_session_id(session.get_session_id().clone());
+//*
+// Play the move of the player whose turn it is, let the computer reply and save the session if the game
+// is over. Shared by the http and websocket routes.
+// The computer searches with no lock held, the search can take a while on big boards
+// */
+pub(crate) async fn play_turn(
+    session_id: &SessionID, active_sessions: &SessionList, player: Player, value: &str, dao: DAO<impl Database>
+) -> Result<SessionView, Error> {
+    let player_input = value.parse::<usize>()
+        .map_err(|_| Error::InvalidMove(MoveError::OutOfRange))?;
+    let (bot, game, version) = {
+        let active_sessions = active_sessions.read().await;
+        let mut session = active_sessions.get(session_id).ok_or(SessionNotExist)?.write().await;
+        auth(&session, player).await?;
+        let status = session.play(player_input).map_err(Error::InvalidMove)?;
+        if status.is_over() {
+            dao.save_session(session.clone()).await;
+            return Ok(SessionView::new(&session));
+        }
+        match session.bot_turn() {
+            Some(bot_turn) => bot_turn,
+            None => return Ok(SessionView::new(&session))
+        }
+    };
 
-    if !auth(session.deref_mut(), player).await {
-        return Err(warp::reject::custom(Error::Unauthorized));
-    }
+    let bot_move = tokio::task::spawn_blocking(move || bot.choose_move(&game))
+        .await
+        .expect("Bot search panicked");
 
-    match params.get("move") {
-        Some(value) => {
-            let mut status : usize = 0;
-            status = session.game.make_a_move(value.parse::<usize>().unwrap());
-            session.status = status;
-            session.turn = (session.turn + 1) % 2;
-
-            match status {
-                1..=3 => {
-                    session.end = true;
-                    dao.save_session(session.clone(), status as i32).await;
-                    Ok(warp::reply::with_status(format!("{} {}", session.status.to_string(), session.game.print()), StatusCode::OK))
-                }
-                _ => Ok(warp::reply::with_status(session.game.print(), StatusCode::OK))
-            }
+    let active_sessions = active_sessions.read().await;
+    let mut session = active_sessions.get(session_id).ok_or(SessionNotExist)?.write().await;
+    if let Some(bot_move) = bot_move {
+        if session.play_bot(version, bot_move).map_err(Error::InvalidMove)?.is_over() {
+            dao.save_session(session.clone()).await;
         }
-        None => Err(warp::reject::custom(Error::AuthenticationFail))
     }
+    Ok(SessionView::new(&session))
 }
 
+//*
+// Tell the player if it's their turn. With ?timeout=<seconds> the request waits for their turn or the
+// end of the game instead of answering right away
+// */
 pub async fn handle_wait_for_move(
-    session_id: String, active_sessions: Arc<RwLock<HashMap<SessionID, RwLock<Session<XO>>>>>,
-    player: Player
+    session_id: String, active_sessions: SessionList,
+    params: HashMap<String, String>, player: Player
 ) -> Result<impl warp::Reply, warp::Rejection> {
     println!("handle wait for move");
-    if let None = active_sessions.read().await.get(&SessionID(session_id.clone())) {
-        return Err(warp::reject::custom(SessionNotExist));
-    }
-    let session = active_sessions.write().await;
-    let session = session.get(&SessionID(session_id)).unwrap();
-    let mut session = session.write().await;
+    let session_id = SessionID(session_id);
+    let changed = match active_sessions.read().await.get(&session_id) {
+        Some(session) => session.read().await.watch(),
+        None => return Err(warp::reject::custom(SessionNotExist))
+    };
+    let (active_sessions, session_id, player) = (&active_sessions, &session_id, &player);
+    long_poll(changed, &params, |timed_out| async move {
+        let active_sessions = active_sessions.read().await;
+        let session = active_sessions.get(session_id).ok_or(warp::reject::custom(SessionNotExist))?.read().await;
+        let your_turn = is_turn_of(&session, player).map_err(warp::reject::custom)?;
+        Ok((your_turn || session.end || timed_out)
+            .then(|| warp::reply::json(&TurnView { your_turn, session: SessionView::new(&session) })))
+    }).await
+}
 
-    if session.can_join() {
-        return Ok(warp::reply::with_status(false.to_string(), StatusCode::OK));
-    }
+//*
+// Hold the request until `reply` has something to say, it is asked again every time `changed` is
+// notified. Once ?timeout= seconds (at most MAX_WAIT_SECONDS, 0 by default) are over it is asked one
+// last time with timed_out and has to reply
+// */
+pub async fn long_poll<R, F>(
+    changed: Arc<Notify>, params: &HashMap<String, String>, mut reply: impl FnMut(bool) -> F
+) -> Result<R, warp::Rejection>
+where F: Future<Output = Result<Option<R>, warp::Rejection>> {
+    let timeout = params.get("timeout")
+        .and_then(|timeout| timeout.parse::<u64>().ok())
+        .unwrap_or(0)
+        .min(MAX_WAIT_SECONDS);
+    let deadline = Instant::now() + Duration::from_secs(timeout);
 
-    if session.end {
-        return Ok(warp::reply::with_status(format!("{} {}", session.status.to_string(), session.game.print()), StatusCode::OK));
+    let mut timed_out = false;
+    loop {
+        //Register before asking, a change in between wakes us up right away
+        let notified = changed.notified();
+        tokio::pin!(notified);
+        notified.as_mut().enable();
+        if let Some(reply) = reply(timed_out).await? {
+            return Ok(reply);
+        }
+        timed_out = tokio::time::timeout_at(deadline, notified).await.is_err();
     }
+}
 
-    if session.players[0].clone().unwrap() == player {
-        if session.turn == 0 {
-            Ok(warp::reply::with_status(session.game.print(), StatusCode::OK))
-        } else {
-            Ok(warp::reply::with_status(false.to_string(), StatusCode::OK))
-        }
-    } else if session.players[1].clone().unwrap() == player {
-        if session.turn == 1 {
-            Ok(warp::reply::with_status(session.game.print(), StatusCode::OK))
-        } else {
-            Ok(warp::reply::with_status(false.to_string(), StatusCode::OK))
-        }
-    } else {
-        Err(warp::reject::custom(Error::Unauthorized))
+#[derive(Serialize)]
+pub struct TurnView {
+    your_turn: bool,
+    session: SessionView,
+}
+
+fn is_turn_of(session: &Session<AnyGame>, player: &Player) -> Result<bool, Error> {
+    match session.player_index(player) {
+        Some(index) => Ok(index == session.turn && !session.can_join() && !session.end),
+        None => Err(Error::Unauthorized)
     }
 }
 
 pub async fn handle_surrender(
-    session_id: String, active_sessions: Arc<RwLock<HashMap<SessionID, RwLock<Session<XO>>>>>,
+    session_id: String, active_sessions: SessionList,
     player: Player, dao: DAO<impl Database>
 ) -> Result<impl warp::Reply, warp::Rejection> {
     println!("handle surrender");
-    if let None = active_sessions.read().await.get(&SessionID(session_id.clone())) {
+    if active_sessions.read().await.get(&SessionID(session_id.clone())).is_none() {
         return Err(warp::reject::custom(SessionNotExist));
     }
     let session = active_sessions.write().await;
     let session = session.get(&SessionID(session_id)).unwrap();
     let mut session = session.write().await;
 
-    if session.players[0].clone().unwrap() == player {
-        //Player 1 at index 0 surrendered so player 2 win
-        session.end = true;
-        dao.save_session(session.clone(), 2).await;
-        Ok(warp::reply::with_status("2", StatusCode::OK))
-    } else if session.players[1].clone().unwrap() == player {
-        //Player 2 at index 1 surrendered so player 1 win
-        session.end = true;
-        dao.save_session(session.clone(), 1).await;
-        Ok(warp::reply::with_status("1", StatusCode::OK))
+    if session.end {
+        return Err(warp::reject::custom(Error::InvalidMove(MoveError::GameOver)));
+    }
+
+    match session.player_index(&player) {
+        //There is nobody to win, and no game to save
+        Some(_) if session.can_join() => Err(warp::reject::custom(Error::InvalidMove(MoveError::NoOpponent))),
+        Some(index) => {
+            //The other player wins
+            session.surrender(index);
+            dao.save_session(session.clone()).await;
+            Ok(warp::reply::json(&SessionView::new(&session)))
+        }
+        None => Err(warp::reject::custom(Error::Unauthorized))
+    }
+}
+
+//*
+// Ask the opponent to take back the last move of the player. The computer always accepts
+// */
+pub async fn handle_takeback_request(
+    session_id: String, active_sessions: SessionList,
+    player: Player
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let active_sessions = active_sessions.read().await;
+    let mut session = match active_sessions.get(&SessionID(session_id)) {
+        Some(session) => session.write().await,
+        None => return Err(warp::reject::custom(SessionNotExist))
+    };
+    let index = session.player_index(&player)
+        .ok_or(warp::reject::custom(Error::Unauthorized))?;
+
+    if session.end {
+        return Err(warp::reject::custom(Error::InvalidMove(MoveError::GameOver)));
+    }
+    if !session.moves.iter().any(|m| m.player == index) {
+        return Err(warp::reject::custom(Error::NoTakeback));
+    }
+
+    if session.bot.is_some() {
+        session.take_back(index);
     } else {
-        Err(warp::reject::custom(Error::Unauthorized))
+        session.takeback_request = Some(index);
+        session.notify(SessionEvent::TakebackRequested { player: index });
     }
+    Ok(warp::reply::json(&SessionView::new(&session)))
 }
 
-//todo implement scoreboard
-pub async fn handle_scoreboard(dao: DAO<impl Database>) -> Result<impl warp::Reply, warp::Rejection> {
-    //format!("| {:<20} | {:<20} | {:<12} \n {}");
-    //Player 1, player 2, status, game board
-    match dao.get_scoreboard().await {
-        Ok(vec) => {
-            let mut vec_result = Vec::new();
-            for session in vec {
-                vec_result.push((
-                    session.players[0].clone().unwrap().get_username(),
-                    session.players[1].clone().unwrap().get_username(),
-                    session.status.to_string(),
-                    session.game.print()
-                ))
+//*
+// The opponent of the player who asked for a takeback accepts or declines it
+// */
+pub async fn handle_takeback_answer(
+    session_id: String, answer: String, active_sessions: SessionList,
+    player: Player
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let active_sessions = active_sessions.read().await;
+    let mut session = match active_sessions.get(&SessionID(session_id)) {
+        Some(session) => session.write().await,
+        None => return Err(warp::reject::custom(SessionNotExist))
+    };
+    let index = session.player_index(&player)
+        .ok_or(warp::reject::custom(Error::Unauthorized))?;
+
+    let requester = match session.takeback_request {
+        Some(requester) if requester != index && !session.end => requester,
+        _ => return Err(warp::reject::custom(Error::NoTakeback))
+    };
+    match answer.as_str() {
+        "accept" => {
+            session.take_back(requester);
+        }
+        "decline" => {
+            session.takeback_request = None;
+            session.notify(SessionEvent::TakebackDeclined { player: requester });
+        }
+        _ => return Err(warp::reject::not_found())
+    }
+    Ok(warp::reply::json(&SessionView::new(&session)))
+}
+
+//*
+// Score every legal move of the current position for the player whose turn it is.
+// Only games against the computer can be analyzed while they are played, other games once they are over.
+// ?ply=n analyzes the position after the first n moves instead of the current one
+// */
+pub async fn handle_analyze(
+    session_id: String, active_sessions: SessionList, player: Player, params: HashMap<String, String>
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let game = match active_sessions.read().await.get(&SessionID(session_id)) {
+        Some(session) => {
+            let session = session.read().await;
+            //Only the players see hints while the game is on, anyone can study it once it's over
+            if !session.end && session.player_index(&player).is_none() {
+                return Err(warp::reject::custom(Error::Unauthorized));
+            }
+            if !session.end && session.bot.is_none() {
+                return Err(warp::reject::custom(Error::AnalysisLocked));
+            }
+            let ply = match params.get("ply") {
+                Some(ply) => ply.parse::<usize>().ok()
+                    .filter(|ply| *ply <= session.moves.len())
+                    .ok_or(warp::reject::custom(Error::InvalidMove(MoveError::OutOfRange)))?,
+                None => session.moves.len()
+            };
+            if session.end && ply == session.moves.len() {
+                return Err(warp::reject::custom(Error::InvalidMove(MoveError::GameOver)));
+            }
+            let mut game = session.game.reset();
+            for m in &session.moves[..ply] {
+                game.make_a_move(m.player_input).map_err(|e| warp::reject::custom(Error::InvalidMove(e)))?;
             }
+            game
+        }
+        None => return Err(warp::reject::custom(SessionNotExist))
+    };
+
+    let analysis = tokio::task::spawn_blocking(move || analysis::analyze(&game))
+        .await
+        .expect("Analysis panicked");
+    Ok(warp::reply::json(&analysis))
+}
+
+#[derive(Serialize)]
+pub struct Replay {
+    session_id: String,
+    game: String,
+    players: Vec<String>,
+    status: SessionStatus,
+    winner: Option<usize>,
+    plies: Vec<ReplayPly>,
+}
+
+#[derive(Serialize)]
+pub struct ReplayPly {
+    ply: usize,
+    player: usize,
+    player_input: usize,
+    played_at: i64,
+    //Board after this move
+    board: Vec<Vec<String>>,
+}
+
+//*
+// Moves of a saved session in order, with the board after each of them
+// */
+pub async fn handle_replay(session_id: i32, dao: DAO<impl Database>) -> Result<impl warp::Reply, warp::Rejection> {
+    let session = match dao.get_saved_session(session_id).await {
+        Ok(Some(session)) => session,
+        Ok(None) => return Err(warp::reject::custom(SessionNotExist)),
+        Err(e) => return Err(warp::reject::custom(DatabaseError(e)))
+    };
+
+    let mut game = session.game.reset();
+    let mut plies = Vec::new();
+    for (ply, m) in session.moves.iter().enumerate() {
+        game.make_a_move(m.player_input).map_err(|e| warp::reject::custom(Error::InvalidMove(e)))?;
+        plies.push(ReplayPly {
+            ply: ply + 1,
+            player: m.player,
+            player_input: m.player_input,
+            played_at: m.played_at,
+            board: game.board(),
+        });
+    }
+
+    Ok(warp::reply::json(&Replay {
+        session_id: session.get_session_id().0,
+        game: session.game.name(),
+        players: session.players.iter().flatten().map(|player| player.get_username()).collect(),
+        status: SessionStatus::new(&session),
+        winner: session.status.winner(),
+        plies,
+    }))
+}
+
+#[derive(Serialize)]
+pub struct ScoreboardEntry {
+    #[serde(flatten)]
+    session: SessionView,
+    replay: String,
+}
 
-            Ok(warp::reply::with_status(serde_json::to_string(&vec_result).unwrap(), StatusCode::OK))
+//*
+// Games with a guest in them are left out unless ?guests=true
+// */
+pub async fn handle_scoreboard(params: HashMap<String, String>, dao: DAO<impl Database>) -> Result<impl warp::Reply, warp::Rejection> {
+    let include_guests = params.get("guests").is_some_and(|guests| guests == "true");
+    match dao.get_scoreboard(include_guests).await {
+        Ok(vec) => {
+            let scoreboard: Vec<ScoreboardEntry> = vec.iter()
+                .map(|session| ScoreboardEntry {
+                    session: SessionView::new(session),
+                    replay: format!("/xogamedev/v1/replays/{}", session.get_session_id().0)
+                })
+                .collect();
+            Ok(warp::reply::json(&scoreboard))
         },
         Err(e) => Err(warp::reject::custom(DatabaseError(e)))
     }
 }
 
-async fn save_and_shutdown(mut session: &mut Session<impl Game + Clone>, status: usize, dao: DAO<impl Database>) {
-    //TODO connect to DB and do shutdown
-    session.end = true;
-    dao.save_session(session.clone(), status as i32).await;
+//*
+// Only the player whose turn it is can move, the other player of the session is told to wait.
+// Nobody moves before both seats are taken
+// */
+async fn auth(session: &Session<impl Game + Clone>, player: Player) -> Result<(), Error> {
+    match session.player_index(&player) {
+        Some(_) if session.can_join() => Err(Error::InvalidMove(MoveError::NoOpponent)),
+        Some(index) if index == session.turn => Ok(()),
+        Some(_) => Err(Error::InvalidMove(MoveError::NotYourTurn)),
+        None => Err(Error::Unauthorized)
+    }
 }
 
-async fn auth(session: &Session<impl Game + Clone>, player: Player) -> bool {
-    return session.players[session.turn].clone().unwrap() == player;
-}
\ No newline at end of file
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tokio::sync::Notify;
+    use crate::ai::{Bot, Difficulty};
+    use crate::error::Error;
+    use crate::game::AnyGame;
+    use crate::game::xo::XO;
+    use crate::model::player::Player;
+    use crate::model::session::{Session, SessionList};
+    use super::{handle_analyze, insert_session, long_poll};
+
+    fn player(username: &str) -> Player {
+        Player::new(username.to_string(), String::new())
+    }
+
+    #[tokio::test]
+    async fn only_players_analyze_a_game_in_progress() {
+        let active_sessions = SessionList::default();
+        let mut session = Session::new(player("alice"), AnyGame::XO(XO::new()));
+        session.bot = Some(Bot::new(Difficulty::Perfect));
+        insert_session(&active_sessions, &mut session).await;
+        let session_id = session.get_session_id().0;
+
+        let outsider = handle_analyze(session_id.clone(), active_sessions.clone(), player("mallory"), HashMap::new()).await;
+        assert!(outsider.is_err_and(|rejection| matches!(rejection.find::<Error>(), Some(Error::Unauthorized))));
+        assert!(handle_analyze(session_id, active_sessions, player("alice"), HashMap::new()).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn long_poll_asks_again_when_notified() {
+        let changed = Arc::new(Notify::new());
+        let notifier = changed.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            notifier.notify_waiters();
+        });
+        let params = HashMap::from([("timeout".to_string(), "60".to_string())]);
+        let mut asked = 0;
+        let reply = long_poll(changed, &params, |timed_out| {
+            asked += 1;
+            let asked = asked;
+            async move { Ok((asked > 1).then_some((asked, timed_out))) }
+        }).await;
+        assert_eq!(reply.ok(), Some((2, false)));
+    }
+
+    #[tokio::test]
+    async fn long_poll_without_timeout_replies_right_away() {
+        let mut asked = 0;
+        let reply = long_poll(Arc::new(Notify::new()), &HashMap::new(), |timed_out| {
+            asked += 1;
+            async move { Ok(timed_out.then_some(())) }
+        }).await;
+        assert!(reply.is_ok());
+        assert_eq!(asked, 2);
+    }
+}
@@ -0,0 +1,113 @@
+use std::collections::HashMap;
+use futures_util::{SinkExt, StreamExt};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::broadcast;
+use warp::ws::{Message, WebSocket, Ws};
+use crate::controller::event_controller::missed_events;
+use crate::controller::session_controller::play_turn;
+use crate::auth::{self, TokenService};
+use crate::dao::{DAO, Database};
+use crate::error::Error;
+use crate::error::Error::SessionNotExist;
+use crate::game::MoveError;
+use crate::model::event::SessionEvent;
+use crate::model::player::Player;
+use crate::model::session::{SessionID, SessionList};
+
+//*
+// Upgrade to a websocket pushing every event of the session to one of its players. Browsers can't set
+// headers on a websocket so the bearer token comes in the query string, ?token=<token>.
+// The player sends {"move": "5"} to play
+// */
+pub async fn handle_socket(
+    session_id: String, active_sessions: SessionList,
+    params: HashMap<String, String>, tokens: TokenService, dao: DAO<impl Database + Clone + Send + 'static>, ws: Ws
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let session_id = SessionID(session_id);
+    let token = params.get("token").ok_or(warp::reject::custom(Error::Unauthorized))?;
+    let player = auth::verify_player(&tokens, token, &dao).await.map_err(warp::reject::custom)?.player();
+
+    let (events, last_sent) = match active_sessions.read().await.get(&session_id) {
+        Some(session) => {
+            let session = session.read().await;
+            if session.player_index(&player).is_none() {
+                return Err(warp::reject::custom(Error::Unauthorized));
+            }
+            (session.subscribe(), session.last_event_id())
+        }
+        None => return Err(warp::reject::custom(SessionNotExist))
+    };
+
+    Ok(ws.on_upgrade(move |socket| game_socket(socket, session_id, player, events, last_sent, active_sessions, dao)))
+}
+
+async fn game_socket(
+    socket: WebSocket, session_id: SessionID, player: Player,
+    mut events: broadcast::Receiver<(u64, SessionEvent)>, mut last_sent: u64, active_sessions: SessionList, dao: DAO<impl Database + Clone + Send>
+) {
+    let (mut sender, mut receiver) = socket.split();
+    //Events the channel dropped are sent again from the log, starting after the last one sent
+    loop {
+        tokio::select! {
+            event = events.recv() => {
+                let events = match event {
+                    Ok((id, _)) if id <= last_sent => continue,
+                    Ok(event) => vec![event],
+                    Err(RecvError::Lagged(_)) => match missed_events(&active_sessions, &session_id, last_sent).await {
+                        Some(missed) => missed,
+                        None => break
+                    },
+                    Err(RecvError::Closed) => break
+                };
+                let mut closed = false;
+                for (id, event) in events {
+                    last_sent = id;
+                    let game_ended = matches!(event, SessionEvent::GameEnded { .. });
+                    if sender.send(Message::text(serde_json::to_string(&event).unwrap())).await.is_err() || game_ended {
+                        closed = true;
+                        break;
+                    }
+                }
+                if closed {
+                    break;
+                }
+            },
+            message = receiver.next() => match message {
+                Some(Ok(message)) if message.is_text() => {
+                    if let Err(e) = socket_move(&session_id, player.clone(), message.to_str().unwrap(), &active_sessions, dao.clone()).await {
+                        let error = serde_json::json!({"event": "error", "message": error_message(e)});
+                        if sender.send(Message::text(error.to_string())).await.is_err() {
+                            break;
+                        }
+                    }
+                }
+                Some(Ok(message)) if message.is_close() => break,
+                Some(Ok(_)) => {}
+                _ => break
+            }
+        }
+    }
+    let _ = sender.close().await;
+}
+
+//The move itself comes back to the player as a move_made event
+async fn socket_move(
+    session_id: &SessionID, player: Player, text: &str,
+    active_sessions: &SessionList, dao: DAO<impl Database + Clone + Send>
+) -> Result<(), Error> {
+    let params = serde_json::from_str::<HashMap<String, String>>(text)
+        .map_err(|_| Error::InvalidMove(MoveError::OutOfRange))?;
+    let value = params.get("move").ok_or(Error::InvalidMove(MoveError::OutOfRange))?;
+
+    play_turn(session_id, active_sessions, player, value, dao).await?;
+    Ok(())
+}
+
+fn error_message(e: Error) -> String {
+    match e {
+        Error::InvalidMove(e) => e.to_string(),
+        Error::Unauthorized => "You are not logged in".to_string(),
+        Error::SessionNotExist => "This session doesn't exist anymore".to_string(),
+        e => format!("{:?}", e)
+    }
+}
@@ -1,21 +1,48 @@
+use std::future::Future;
+use std::time::Duration;
 use sqlx::Error;
-use sqlx::postgres::PgRow;
 use crate::game::Game;
 use crate::model::player::Player;
 use crate::model::session::Session;
 
 pub mod postgres;
 
-pub trait Database {
-    async fn login(&self, player: Player) -> Result<bool, Error>;
+pub trait Database: Sync {
+    //Player id if the password is right
+    async fn login(&self, player: Player) -> Result<Option<i32>, Error>;
 
+    //false if the username is taken
     async fn register(&self, player: Player) -> Result<bool, Error>;
-    async fn save_session(&self, session: Session<impl Game + Clone>, result: i32);
-    //Default to deserialize XO game
-    async fn get_scoreboard(&self) -> Result<Vec<Session<impl Game + Clone>>, Error>;
+
+    async fn player_exists(&self, username: String) -> Result<bool, Error>;
+    //Checked on every authenticated request, Send so the auth filter can await it
+    fn player_id(&self, username: String) -> impl Future<Output = Result<Option<i32>, Error>> + Send;
+
+    //Player id, None if the generated username is taken
+    async fn create_guest(&self, player: Player) -> Result<Option<i32>, Error>;
+    //*
+    // The guest becomes a registered player with the username and password they chose, their games
+    // follow them. None if the username is taken
+    // */
+    async fn upgrade_guest(&self, username: String, player: Player) -> Result<Option<i32>, Error>;
+
+    async fn change_password(&self, username: String, password: String) -> Result<(), Error>;
+    //Player id stays the same, None if the new username is taken
+    async fn rename(&self, username: String, new_username: String) -> Result<Option<i32>, Error>;
+    //Saved sessions of the player stay on the scoreboard under Player::DELETED_USERNAME
+    async fn delete_player(&self, username: String) -> Result<(), Error>;
+    //Guests made longer ago than this, how many were deleted. Their saved sessions stay
+    async fn delete_guests_older_than(&self, age: Duration) -> Result<u64, Error>;
+    //Send so games can also be saved from websocket tasks
+    fn save_session(&self, session: Session<impl Game + Clone + Send>) -> impl Future<Output = ()> + Send;
+    //Sessions with a guest in them only if include_guests
+    async fn get_scoreboard(&self, include_guests: bool) -> Result<Vec<Session<impl Game + Clone>>, Error>;
+    //Saved session with its moves
+    async fn get_saved_session(&self, session_id: i32) -> Result<Option<Session<impl Game + Clone>>, Error>;
 }
 
 #[derive(Clone)]
+#[allow(clippy::upper_case_acronyms)]
 pub struct DAO<T: Database> {
     database: T
 }
@@ -31,16 +58,52 @@ impl<T: Database> DAO<T> {
         self.database.register(player).await
     }
 
-    pub async fn login(&self, player: Player) -> Result<bool, sqlx::Error> {
+    pub async fn player_exists(&self, username: String) -> Result<bool, sqlx::Error> {
+        self.database.player_exists(username).await
+    }
+
+    pub async fn player_id(&self, username: String) -> Result<Option<i32>, sqlx::Error> {
+        self.database.player_id(username).await
+    }
+
+    pub async fn login(&self, player: Player) -> Result<Option<i32>, sqlx::Error> {
         self.database.login(player).await
     }
 
-    pub async fn save_session(self, session: Session<impl Game + Clone>, result: i32) {
-        self.database.save_session(session, result).await
+    pub async fn create_guest(&self, player: Player) -> Result<Option<i32>, sqlx::Error> {
+        self.database.create_guest(player).await
+    }
+
+    pub async fn upgrade_guest(&self, username: String, player: Player) -> Result<Option<i32>, sqlx::Error> {
+        self.database.upgrade_guest(username, player).await
+    }
+
+    pub async fn change_password(&self, username: String, password: String) -> Result<(), sqlx::Error> {
+        self.database.change_password(username, password).await
+    }
+
+    pub async fn rename(&self, username: String, new_username: String) -> Result<Option<i32>, sqlx::Error> {
+        self.database.rename(username, new_username).await
+    }
+
+    pub async fn delete_player(&self, username: String) -> Result<(), sqlx::Error> {
+        self.database.delete_player(username).await
+    }
+
+    pub async fn delete_guests_older_than(&self, age: Duration) -> Result<u64, sqlx::Error> {
+        self.database.delete_guests_older_than(age).await
+    }
+
+    pub async fn save_session(self, session: Session<impl Game + Clone + Send>) {
+        self.database.save_session(session).await
+    }
+
+    pub async fn get_scoreboard<'a>(&'a self, include_guests: bool) -> Result<Vec<Session<impl Game + Clone +'a>>, Error> {
+        self.database.get_scoreboard(include_guests).await
     }
 
-    pub async fn get_scoreboard<'a>(&'a self) -> Result<Vec<Session<impl Game + Clone +'a>>, Error> {
-        self.database.get_scoreboard().await
+    pub async fn get_saved_session<'a>(&'a self, session_id: i32) -> Result<Option<Session<impl Game + Clone + 'a>>, Error> {
+        self.database.get_saved_session(session_id).await
     }
 
 }
\ No newline at end of file
@@ -1,11 +1,13 @@
+use std::time::Duration;
 use log::error;
-use crate::Game;
+use crate::game::Game;
 use sqlx::{Error, PgPool, Row};
 use sqlx::postgres::{PgPoolOptions, PgRow};
+use crate::auth::password::{self, Verification};
 use crate::dao::Database;
-use crate::game::xo::XO;
+use crate::game::AnyGame;
 use crate::model::player::Player;
-use crate::model::session::{Session, SessionID};
+use crate::model::session::{Move, Session};
 
 #[derive(Clone)]
 pub struct PostgresDB {
@@ -19,51 +21,233 @@ impl PostgresDB {
             pool
         }
     }
+
+    //Connects on first use and gives up quickly, so the routes can be built without a database
+    #[cfg(test)]
+    pub fn lazy(db_url: &str) -> PostgresDB {
+        let pool = PgPoolOptions::new()
+            .acquire_timeout(std::time::Duration::from_millis(100))
+            .connect_lazy(db_url)
+            .expect("Database url failed");
+        PostgresDB {
+            pool
+        }
+    }
 }
 
 impl Database for PostgresDB {
-    async fn login(&self, player: Player) -> Result<bool, Error> {
-        match sqlx::query("select * from player where username = $1 and password = $2")
+    async fn login(&self, player: Player) -> Result<Option<i32>, Error> {
+        let (player_id, stored): (i32, String) = match sqlx::query("select player_id, password from player where username = $1")
             .bind(player.get_username())
-            .bind(player.get_password())
-            .map(|row: PgRow| {
-                Player::new(row.get("username"), row.get("password"));
-            })
+            .map(|row: PgRow| (row.get("player_id"), row.get("password")))
             .fetch_optional(&self.pool)
-            .await {
-            Ok(result) => match result {
-                Some(_) => Ok(true),
-                None => Ok(false)
-            },
-            Err(e) => Err(e)
+            .await? {
+            Some(row) => row,
+            None => return Ok(None)
+        };
+
+        //Argon2 is slow on purpose, keep it off the async workers
+        let password = player.get_password();
+        let verification = tokio::task::spawn_blocking(move || password::verify(&password, &stored))
+            .await
+            .expect("Password verification panicked");
+        match verification {
+            Verification::Valid => Ok(Some(player_id)),
+            Verification::ValidPlaintext => {
+                //Saved before passwords were hashed, hash it now that we know it
+                let password = player.get_password();
+                let hash = tokio::task::spawn_blocking(move || password::hash(&password))
+                    .await
+                    .expect("Password hashing panicked");
+                sqlx::query("update player set password = $1 where username = $2")
+                    .bind(hash)
+                    .bind(player.get_username())
+                    .execute(&self.pool)
+                    .await?;
+                Ok(Some(player_id))
+            }
+            Verification::Invalid => Ok(None)
         }
     }
 
     async fn register(&self, player: Player) -> Result<bool, Error> {
+        let password = player.get_password();
+        let hash = tokio::task::spawn_blocking(move || password::hash(&password))
+            .await
+            .expect("Password hashing panicked");
         match sqlx::query("insert into player (username, password) values \
             ($1, $2)")
             .bind(player.get_username())
-            .bind(player.get_password())
+            .bind(hash)
             .execute(&self.pool)
             .await {
             Ok(_) => Ok(true),
+            Err(Error::Database(e)) if e.is_unique_violation() => Ok(false),
             Err(e) => Err(e)
         }
     }
 
-    //Default to save XO game
-    async fn save_session(&self, session: Session<impl Game + Clone>, result: i32) {
-        match sqlx::query("insert into session(player1_username, player2_username, result, board)\
-        values ($1, $2, $3, $4)")
+    async fn player_exists(&self, username: String) -> Result<bool, Error> {
+        sqlx::query("select exists (select 1 from player where username = $1) as found")
+            .bind(username)
+            .map(|row: PgRow| row.get("found"))
+            .fetch_one(&self.pool)
+            .await
+    }
+
+    async fn player_id(&self, username: String) -> Result<Option<i32>, Error> {
+        sqlx::query("select player_id from player where username = $1")
+            .bind(username)
+            .map(|row: PgRow| row.get("player_id"))
+            .fetch_optional(&self.pool)
+            .await
+    }
+
+    async fn create_guest(&self, player: Player) -> Result<Option<i32>, Error> {
+        let password = player.get_password();
+        let hash = tokio::task::spawn_blocking(move || password::hash(&password))
+            .await
+            .expect("Password hashing panicked");
+        match sqlx::query("insert into player (username, password, guest) values \
+            ($1, $2, true) returning player_id")
+            .bind(player.get_username())
+            .bind(hash)
+            .fetch_one(&self.pool)
+            .await {
+            Ok(row) => Ok(Some(row.get("player_id"))),
+            Err(Error::Database(e)) if e.is_unique_violation() => Ok(None),
+            Err(e) => Err(e)
+        }
+    }
+
+    async fn upgrade_guest(&self, username: String, player: Player) -> Result<Option<i32>, Error> {
+        let password = player.get_password();
+        let hash = tokio::task::spawn_blocking(move || password::hash(&password))
+            .await
+            .expect("Password hashing panicked");
+        let mut transaction = self.pool.begin().await?;
+        let player_id: i32 = match sqlx::query("update player set username = $1, password = $2, guest = false \
+            where username = $3 and guest returning player_id")
+            .bind(player.get_username())
+            .bind(hash)
+            .bind(&username)
+            .fetch_one(&mut *transaction)
+            .await {
+            Ok(row) => row.get("player_id"),
+            Err(Error::Database(e)) if e.is_unique_violation() => return Ok(None),
+            Err(e) => return Err(e)
+        };
+        //Games played as a guest are kept under the new username
+        sqlx::query("update session set player1_username = $1 where player1_username = $2")
+            .bind(player.get_username())
+            .bind(&username)
+            .execute(&mut *transaction)
+            .await?;
+        sqlx::query("update session set player2_username = $1 where player2_username = $2")
+            .bind(player.get_username())
+            .bind(&username)
+            .execute(&mut *transaction)
+            .await?;
+        transaction.commit().await?;
+        Ok(Some(player_id))
+    }
+
+    async fn change_password(&self, username: String, password: String) -> Result<(), Error> {
+        let hash = tokio::task::spawn_blocking(move || password::hash(&password))
+            .await
+            .expect("Password hashing panicked");
+        sqlx::query("update player set password = $1 where username = $2")
+            .bind(hash)
+            .bind(username)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn rename(&self, username: String, new_username: String) -> Result<Option<i32>, Error> {
+        let mut transaction = self.pool.begin().await?;
+        let player_id: i32 = match sqlx::query("update player set username = $1 where username = $2 returning player_id")
+            .bind(&new_username)
+            .bind(&username)
+            .fetch_one(&mut *transaction)
+            .await {
+            Ok(row) => row.get("player_id"),
+            Err(Error::Database(e)) if e.is_unique_violation() => return Ok(None),
+            Err(e) => return Err(e)
+        };
+        //Saved sessions only have the username, they follow the player
+        sqlx::query("update session set player1_username = $1 where player1_username = $2")
+            .bind(&new_username)
+            .bind(&username)
+            .execute(&mut *transaction)
+            .await?;
+        sqlx::query("update session set player2_username = $1 where player2_username = $2")
+            .bind(&new_username)
+            .bind(&username)
+            .execute(&mut *transaction)
+            .await?;
+        transaction.commit().await?;
+        Ok(Some(player_id))
+    }
+
+    async fn delete_player(&self, username: String) -> Result<(), Error> {
+        let mut transaction = self.pool.begin().await?;
+        sqlx::query("update session set player1_username = $1 where player1_username = $2")
+            .bind(Player::DELETED_USERNAME)
+            .bind(&username)
+            .execute(&mut *transaction)
+            .await?;
+        sqlx::query("update session set player2_username = $1 where player2_username = $2")
+            .bind(Player::DELETED_USERNAME)
+            .bind(&username)
+            .execute(&mut *transaction)
+            .await?;
+        sqlx::query("delete from player where username = $1")
+            .bind(&username)
+            .execute(&mut *transaction)
+            .await?;
+        transaction.commit().await
+    }
+
+    async fn delete_guests_older_than(&self, age: Duration) -> Result<u64, Error> {
+        sqlx::query("delete from player where guest and created_on < now() - make_interval(secs => $1)")
+            .bind(age.as_secs_f64())
+            .execute(&self.pool)
+            .await
+            .map(|result| result.rows_affected())
+    }
+
+    async fn save_session(&self, session: Session<impl Game + Clone + Send>) {
+        let session_id: i32 = match sqlx::query("insert into session(player1_username, player2_username, result, game, board, bot)\
+        values ($1, $2, $3, $4, $5, $6) returning session_id")
             .bind(session.players[0].clone().unwrap().get_username())
             .bind(session.players[1].clone().unwrap().get_username())
-            .bind(result)
+            .bind(session.status.code().to_string())
+            .bind(session.game.name())
             .bind(session.game.to_string())
+            .bind(session.bot.as_ref().map(|bot| bot.difficulty().to_string()))
+            .fetch_one(&self.pool)
+            .await {
+            Ok(row) => row.get("session_id"),
+            Err(e) => {
+                error!("Database error {}", e);
+                return;
+            }
+            //TODO implement this
+        };
+        //One row per move, played_at is saved in milliseconds since epoch
+        match sqlx::query("insert into move(session_id, ply, player, player_input, played_at) \
+        select $1, ply, player, player_input, to_timestamp(played_at / 1000.0) \
+        from unnest($2::int[], $3::int[], $4::int[], $5::bigint[]) as m(ply, player, player_input, played_at)")
+            .bind(session_id)
+            .bind((1..=session.moves.len() as i32).collect::<Vec<i32>>())
+            .bind(session.moves.iter().map(|m| m.player as i32).collect::<Vec<i32>>())
+            .bind(session.moves.iter().map(|m| m.player_input as i32).collect::<Vec<i32>>())
+            .bind(session.moves.iter().map(|m| m.played_at).collect::<Vec<i64>>())
             .execute(&self.pool)
             .await {
             Ok(_) => {}
             Err(e) => {error!("Database error {}", e)}
-            //TODO implement this
         };
         sqlx::query("delete from session where created_on not in (select created_on from session order by created_on desc limit 50)")
             .execute(&self.pool)
@@ -71,24 +255,53 @@ impl Database for PostgresDB {
             .expect("Failed to delete to 50 session records");
     }
 
-    //Default to deserialize XO game
-    async fn get_scoreboard(&self) -> Result<Vec<Session<impl Game + Clone>>, Error> {
-        match sqlx::query("select * from session order by created_on desc")
-            .map(|pg_row: PgRow| {
-                Session::<XO>::new_session_for_scoreboard(
-                    pg_row.get("session_id"),
-                    [
-                        Some(Player::new(pg_row.get("player1_username"), String::new())),
-                        Some(Player::new(pg_row.get("player1_username"), String::new()))
-                    ],
-                    pg_row.get("result"),
-                    pg_row.get("board")
-                )
-            })
+    async fn get_scoreboard(&self, include_guests: bool) -> Result<Vec<Session<impl Game + Clone>>, Error> {
+        //Guests are told apart by their generated names, their rows are deleted after a while
+        match sqlx::query("select * from session s where $1 or not \
+            (s.player1_username like $2 or s.player2_username like $2) \
+            order by created_on desc")
+            .bind(include_guests)
+            .bind(format!("{}%", Player::GUEST_PREFIX))
+            .map(session_from_row)
             .fetch_all(&self.pool)
             .await {
             Ok(vector) => Ok(vector),
             Err(e) => Err(e)
         }
     }
-}
\ No newline at end of file
+
+    async fn get_saved_session(&self, session_id: i32) -> Result<Option<Session<impl Game + Clone>>, Error> {
+        let mut session = match sqlx::query("select * from session where session_id = $1")
+            .bind(session_id)
+            .map(session_from_row)
+            .fetch_optional(&self.pool)
+            .await? {
+            Some(session) => session,
+            None => return Ok(None)
+        };
+        session.moves = sqlx::query("select player, player_input, \
+        (extract(epoch from played_at) * 1000)::bigint as played_at from move where session_id = $1 order by ply")
+            .bind(session_id)
+            .map(|pg_row: PgRow| Move {
+                player: pg_row.get::<i32, _>("player") as usize,
+                player_input: pg_row.get::<i32, _>("player_input") as usize,
+                played_at: pg_row.get("played_at"),
+            })
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(Some(session))
+    }
+}
+
+fn session_from_row(pg_row: PgRow) -> Session<AnyGame> {
+    Session::<AnyGame>::new_session_for_scoreboard(
+        pg_row.get("session_id"),
+        [
+            Some(Player::new(pg_row.get("player1_username"), String::new())),
+            Some(Player::new(pg_row.get("player2_username"), String::new()))
+        ],
+        pg_row.get("result"),
+        pg_row.get("board"),
+        pg_row.get("bot")
+    )
+}
@@ -1,12 +1,90 @@
+use warp::http::StatusCode;
 use warp::reject::Reject;
+use crate::game::MoveError;
 
 #[derive(Debug)]
+#[allow(clippy::enum_variant_names)]
 pub enum Error {
-    InvalidMove,
+    InvalidMove(MoveError),
+    InvalidGameOption,
     AuthenticationFail,
     SessionNotExist,
+    //Challenged someone without an account
+    PlayerNotExist,
+    SessionFull,
+    //Private session joined by id by someone who wasn't invited
+    NotInvited,
+    NoTakeback,
+    //Analysis of a game still played between two people would help one of them cheat
+    AnalysisLocked,
+    //Waiting for or leaving the matchmaking queue without being in it
+    NotQueued,
     Unauthorized,
-    DatabaseError(sqlx::Error)
+    //Only a guest account can be upgraded
+    NotAGuest,
+    //A guest has to upgrade their account to pick a username
+    GuestAccount,
+    DatabaseError(sqlx::Error),
+    //Registration and account changes, see auth::validation
+    UsernameTooShort { min: usize },
+    UsernameTooLong { max: usize },
+    UsernameInvalidCharacters,
+    UsernameReserved,
+    UsernameTaken,
+    PasswordTooShort { min: usize },
+    PasswordTooLong { max: usize },
+    PasswordTooWeak { min_classes: usize },
+    //Login refused before checking the password, seconds until the next attempt can go through
+    TooManyAttempts { retry_after: u64 },
 }
 
-impl Reject for Error {}
\ No newline at end of file
+impl Error {
+    //*
+    // Machine readable reason and message of a rejected username or password, None for the other errors.
+    // Clients switch on the reason, the message is for people
+    // */
+    pub fn validation_failure(&self) -> Option<(&'static str, String)> {
+        match self {
+            Error::UsernameTooShort { min } => Some(("username_too_short", format!("Username needs at least {} characters", min))),
+            Error::UsernameTooLong { max } => Some(("username_too_long", format!("Username can't be longer than {} characters", max))),
+            Error::UsernameInvalidCharacters => Some(("username_invalid_characters",
+                "Username can only have letters, digits, '_', '-' and '.'".to_string())),
+            Error::UsernameReserved => Some(("username_reserved", "This username is reserved".to_string())),
+            Error::UsernameTaken => Some(("username_taken", "This username is already taken".to_string())),
+            Error::PasswordTooShort { min } => Some(("password_too_short", format!("Password needs at least {} characters", min))),
+            Error::PasswordTooLong { max } => Some(("password_too_long", format!("Password can't be longer than {} characters", max))),
+            Error::PasswordTooWeak { min_classes } => Some(("password_too_weak", format!(
+                "Password needs characters of at least {} kinds among lowercase, uppercase, digits and symbols", min_classes
+            ))),
+            _ => None
+        }
+    }
+}
+
+impl Reject for Error {}
+
+impl MoveError {
+    //A move that can never be played is a bad request, a move that can't be played right now is a conflict
+    pub fn status(&self) -> StatusCode {
+        match self {
+            MoveError::OutOfRange | MoveError::WrongBoard => StatusCode::BAD_REQUEST,
+            MoveError::CellOccupied | MoveError::NotYourTurn | MoveError::NoOpponent | MoveError::GameOver => StatusCode::CONFLICT
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use warp::http::StatusCode;
+    use crate::game::MoveError;
+
+    #[test]
+    fn move_errors_map_to_bad_request_or_conflict() {
+        assert_eq!(MoveError::OutOfRange.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(MoveError::WrongBoard.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(MoveError::CellOccupied.status(), StatusCode::CONFLICT);
+        assert_eq!(MoveError::NotYourTurn.status(), StatusCode::CONFLICT);
+        assert_eq!(MoveError::NoOpponent.status(), StatusCode::CONFLICT);
+        assert_eq!(MoveError::GameOver.status(), StatusCode::CONFLICT);
+    }
+}
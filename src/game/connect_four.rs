@@ -0,0 +1,153 @@
+use serde::{Deserialize, Serialize};
+use crate::game::{Game, MoveError, MoveOutcome};
+use crate::game::xo::XO;
+
+//*
+// Connect Four: players drop their mark into one of the 7 columns, it falls to the lowest empty
+// cell of that column. The first player to connect 4 marks in a row wins
+// */
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ConnectFour {
+    board: Vec<Vec<String>>,
+    number_of_move: i32,
+    is_x: bool,
+    #[serde(default)]
+    over: bool,
+}
+
+impl ConnectFour {
+    const ROWS: usize = 6;
+    const COLS: usize = 7;
+    const K: usize = 4;
+
+    pub fn new() -> ConnectFour {
+        ConnectFour {
+            board: vec![vec![" ".to_string(); Self::COLS]; Self::ROWS],
+            number_of_move: 0,
+            is_x: true,
+            over: false,
+        }
+    }
+}
+
+impl Game for ConnectFour {
+    //*
+    // Player input is the column from 1 to 7, a full column is reported as an occupied cell
+    // */
+    fn make_a_move(&mut self, player_input: usize) -> Result<MoveOutcome, MoveError> {
+        if self.over {
+            return Err(MoveError::GameOver);
+        }
+        if player_input == 0 || player_input > Self::COLS {
+            return Err(MoveError::OutOfRange);
+        }
+
+        let col = player_input - 1;
+        //the mark falls to the lowest empty cell
+        let row = match (0..Self::ROWS).rev().find(|row| self.board[*row][col] == " ") {
+            Some(row) => row,
+            None => return Err(MoveError::CellOccupied)
+        };
+
+        if self.is_x {
+            self.is_x = false;
+            self.board[row][col] = "X".to_string();
+        } else {
+            self.is_x = true;
+            self.board[row][col] = "O".to_string();
+        }
+        self.number_of_move += 1;
+
+        if XO::check(&self.board, row, col, Self::K) {
+            self.over = true;
+            if !self.is_x {
+                return Ok(MoveOutcome::Win(0));
+            } else {
+                return Ok(MoveOutcome::Win(1));
+            }
+        }
+        if self.number_of_move as usize == Self::ROWS * Self::COLS {
+            self.over = true;
+            return Ok(MoveOutcome::Draw);
+        }
+        Ok(MoveOutcome::Continue)
+    }
+
+    fn legal_moves(&self) -> Vec<usize> {
+        if self.over {
+            return Vec::new();
+        }
+        //a column is playable while its top cell is empty
+        (1..=Self::COLS).filter(|col| self.board[0][col - 1] == " ").collect()
+    }
+
+    fn reset(&self) -> Self {
+        ConnectFour::new()
+    }
+
+    fn board(&self) -> Vec<Vec<String>> {
+        self.board.clone()
+    }
+
+    fn to_string(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+
+    fn from_string(board: &str) -> Self {
+        serde_json::from_str::<ConnectFour>(board).unwrap()
+    }
+
+    fn name(&self) -> String { "ConnectFour".to_string() }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::game::{Game, MoveError, MoveOutcome};
+    use crate::game::testing::play;
+    use super::ConnectFour;
+
+    #[test]
+    fn marks_fall_to_the_lowest_empty_cell() {
+        let mut game = ConnectFour::new();
+        play(&mut game, &[4, 4, 3]);
+        let board = game.board();
+        assert_eq!(board[5][3], "X");
+        assert_eq!(board[4][3], "O");
+        assert_eq!(board[5][2], "X");
+        assert_eq!(board[4][2], " ");
+    }
+
+    #[test]
+    fn full_column_is_refused() {
+        let mut game = ConnectFour::new();
+        play(&mut game, &[1, 1, 1, 1, 1, 1]);
+        assert_eq!(game.make_a_move(1), Err(MoveError::CellOccupied));
+        assert_eq!(game.legal_moves(), vec![2, 3, 4, 5, 6, 7]);
+        //X is still to move
+        game.make_a_move(2).unwrap();
+        assert_eq!(game.board()[5][1], "X");
+    }
+
+    #[test]
+    fn columns_are_numbered_from_1_to_7() {
+        let mut game = ConnectFour::new();
+        assert_eq!(game.make_a_move(0), Err(MoveError::OutOfRange));
+        assert_eq!(game.make_a_move(8), Err(MoveError::OutOfRange));
+    }
+
+    #[test]
+    fn four_stacked_marks_win() {
+        let mut game = ConnectFour::new();
+        assert_eq!(play(&mut game, &[1, 2, 1, 2, 1, 2]), MoveOutcome::Continue);
+        assert_eq!(game.make_a_move(1), Ok(MoveOutcome::Win(0)));
+        assert_eq!(game.make_a_move(3), Err(MoveError::GameOver));
+    }
+
+    #[test]
+    fn diagonal_built_on_other_marks_wins() {
+        let mut game = ConnectFour::new();
+        //O climbs from column 1 to column 4 on top of the other marks
+        assert_eq!(play(&mut game, &[2, 1, 3, 2, 3, 3, 4, 4, 4, 7, 7]), MoveOutcome::Continue);
+        assert_eq!(game.make_a_move(4), Ok(MoveOutcome::Win(1)));
+    }
+}
@@ -1,13 +1,195 @@
+use std::fmt::{Display, Formatter};
+use serde::{Deserialize, Serialize};
+use crate::game::connect_four::ConnectFour;
+use crate::game::ultimate_xo::UltimateXO;
+use crate::game::xo::XO;
+
+pub mod connect_four;
+pub mod ultimate_xo;
 pub mod xo;
+//Helpers shared by the tests of the games and the bots
+#[cfg(test)]
+pub mod testing;
+
+
+//*
+// Result of a valid move. Players are numbered like Session::players, 0 is the first player to move
+// */
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Debug)]
+pub enum MoveOutcome {
+    Continue,
+    Win(usize),
+    Draw,
+}
+
+impl MoveOutcome {
+    //Code saved in the result column of the session table: 1 if player 1 win, 2 if player 2 win,
+    //3 if draw and 0 if the game is not over
+    pub fn code(&self) -> i32 {
+        match self {
+            MoveOutcome::Continue => 0,
+            MoveOutcome::Win(player) => *player as i32 + 1,
+            MoveOutcome::Draw => 3,
+        }
+    }
+
+    pub fn from_code(code: i32) -> Self {
+        match code {
+            1 => MoveOutcome::Win(0),
+            2 => MoveOutcome::Win(1),
+            3 => MoveOutcome::Draw,
+            _ => MoveOutcome::Continue,
+        }
+    }
 
+    pub fn is_over(&self) -> bool {
+        *self != MoveOutcome::Continue
+    }
+
+    pub fn winner(&self) -> Option<usize> {
+        match self {
+            MoveOutcome::Win(player) => Some(*player),
+            _ => None
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MoveError {
+    CellOccupied,
+    OutOfRange,
+    //Ultimate XO only, the move is not in the board the player was sent to
+    WrongBoard,
+    NotYourTurn,
+    //Nobody joined the session yet, or the challenged player didn't accept
+    NoOpponent,
+    GameOver,
+}
+
+impl Display for MoveError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MoveError::CellOccupied => write!(f, "This cell is already taken"),
+            MoveError::OutOfRange => write!(f, "This move is not on the board"),
+            MoveError::WrongBoard => write!(f, "You have to play in the board you were sent to"),
+            MoveError::NotYourTurn => write!(f, "It's not your turn"),
+            MoveError::NoOpponent => write!(f, "Wait for an opponent to join"),
+            MoveError::GameOver => write!(f, "The game is already over"),
+        }
+    }
+}
 
 pub trait Game {
     //*
-    // Main function of the XO return 1 if player 1 win, 2 if player 2 win, 0 if nothing happened
-    // 3 if draw
+    // Main function of the game, play the input of the player whose turn it is. An invalid move
+    // doesn't change the game
     // */
-    fn make_a_move(&mut self, player_input: usize) -> usize;
-    fn print(&self) -> String;
+    fn make_a_move(&mut self, player_input: usize) -> Result<MoveOutcome, MoveError>;
+    //Every input make_a_move would accept right now, empty when the game is over
+    fn legal_moves(&self) -> Vec<usize>;
+    //A new game with the same settings, used to replay a list of moves
+    fn reset(&self) -> Self;
+    //Cells row by row, " " for an empty cell, for clients drawing the board themselves
+    fn board(&self) -> Vec<Vec<String>>;
     fn to_string(&self) -> String;
     fn from_string(string: &str) -> Self;
-}
\ No newline at end of file
+    //Name of the game, saved alongside the board. Tells apart the sizes of games that have more than one
+    fn name(&self) -> String;
+    //Games made of smaller boards only: cell of each small board, see UltimateXO
+    fn meta_board(&self) -> Option<Vec<Vec<String>>> { None }
+    //Games made of smaller boards only: small board the next move goes in, numbered from 1 like the
+    //player input. None when it can go in any of them
+    fn next_board(&self) -> Option<usize> { None }
+}
+
+//*
+// Every game the server can host, so sessions of different games can live in the same session list
+// */
+#[derive(Clone, Serialize, Deserialize)]
+pub enum AnyGame {
+    XO(XO),
+    ConnectFour(ConnectFour),
+    UltimateXO(UltimateXO),
+}
+
+impl Game for AnyGame {
+    fn make_a_move(&mut self, player_input: usize) -> Result<MoveOutcome, MoveError> {
+        match self {
+            AnyGame::XO(game) => game.make_a_move(player_input),
+            AnyGame::ConnectFour(game) => game.make_a_move(player_input),
+            AnyGame::UltimateXO(game) => game.make_a_move(player_input),
+        }
+    }
+
+    fn legal_moves(&self) -> Vec<usize> {
+        match self {
+            AnyGame::XO(game) => game.legal_moves(),
+            AnyGame::ConnectFour(game) => game.legal_moves(),
+            AnyGame::UltimateXO(game) => game.legal_moves(),
+        }
+    }
+
+    fn reset(&self) -> Self {
+        match self {
+            AnyGame::XO(game) => AnyGame::XO(game.reset()),
+            AnyGame::ConnectFour(game) => AnyGame::ConnectFour(game.reset()),
+            AnyGame::UltimateXO(game) => AnyGame::UltimateXO(game.reset()),
+        }
+    }
+
+    fn board(&self) -> Vec<Vec<String>> {
+        match self {
+            AnyGame::XO(game) => game.board(),
+            AnyGame::ConnectFour(game) => game.board(),
+            AnyGame::UltimateXO(game) => game.board(),
+        }
+    }
+
+    fn to_string(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+
+    fn from_string(string: &str) -> Self {
+        //Boards saved before we had more than one game are plain XO
+        serde_json::from_str::<AnyGame>(string).unwrap_or_else(|_| AnyGame::XO(XO::from_string(string)))
+    }
+
+    fn name(&self) -> String {
+        match self {
+            AnyGame::XO(game) => game.name(),
+            AnyGame::ConnectFour(game) => game.name(),
+            AnyGame::UltimateXO(game) => game.name(),
+        }
+    }
+
+    fn meta_board(&self) -> Option<Vec<Vec<String>>> {
+        match self {
+            AnyGame::XO(game) => game.meta_board(),
+            AnyGame::ConnectFour(game) => game.meta_board(),
+            AnyGame::UltimateXO(game) => game.meta_board(),
+        }
+    }
+
+    fn next_board(&self) -> Option<usize> {
+        match self {
+            AnyGame::XO(game) => game.next_board(),
+            AnyGame::ConnectFour(game) => game.next_board(),
+            AnyGame::UltimateXO(game) => game.next_board(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MoveOutcome;
+
+    #[test]
+    fn outcome_codes_round_trip() {
+        for outcome in [MoveOutcome::Continue, MoveOutcome::Win(0), MoveOutcome::Win(1), MoveOutcome::Draw] {
+            assert_eq!(MoveOutcome::from_code(outcome.code()), outcome);
+        }
+        assert_eq!(MoveOutcome::Win(1).code(), 2);
+        assert_eq!(MoveOutcome::Draw.winner(), None);
+        assert!(!MoveOutcome::Continue.is_over());
+    }
+}
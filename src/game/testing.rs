@@ -0,0 +1,22 @@
+use crate::game::{Game, MoveOutcome};
+
+//Play the inputs in order and return the outcome of the last one, Continue without any input
+pub fn play(game: &mut impl Game, inputs: &[usize]) -> MoveOutcome {
+    inputs.iter().fold(MoveOutcome::Continue, |_, input| game.make_a_move(*input).unwrap())
+}
+
+//*
+// Play every move of the opponent and every move the searcher picks until the end of the game,
+// false if any of those games is lost by the searcher
+// */
+pub fn never_loses<G: Game + Clone>(game: &G, searcher_to_move: bool, searcher_moves: &impl Fn(&G) -> Vec<usize>) -> bool {
+    let moves = if searcher_to_move { searcher_moves(game) } else { game.legal_moves() };
+    moves.into_iter().all(|input| {
+        let mut child = game.clone();
+        match child.make_a_move(input).unwrap() {
+            MoveOutcome::Continue => never_loses(&child, !searcher_to_move, searcher_moves),
+            MoveOutcome::Win(_) => searcher_to_move,
+            MoveOutcome::Draw => true,
+        }
+    })
+}
@@ -0,0 +1,207 @@
+use serde::{Deserialize, Serialize};
+use crate::game::{Game, MoveError, MoveOutcome};
+use crate::game::xo::XO;
+
+//*
+// Ultimate XO: 9 small XO boards laid out as a 3x3 meta board. Winning a small board claims its
+// cell on the meta board, and the first player to win the meta board wins the game.
+// The cell you play in a small board sends your opponent to the matching small board, if that board
+// is already decided they can play anywhere
+// */
+#[derive(Clone, Serialize, Deserialize)]
+pub struct UltimateXO {
+    boards: Vec<Vec<Vec<String>>>,
+    //" " while the small board is being played, "X" or "O" for its winner and "-" for a draw
+    meta: Vec<Vec<String>>,
+    next_board: Option<usize>,
+    number_of_move: i32,
+    is_x: bool,
+    #[serde(default)]
+    over: bool,
+}
+
+impl UltimateXO {
+    pub fn new() -> UltimateXO {
+        UltimateXO {
+            boards: vec![vec![vec![" ".to_string(); 3]; 3]; 9],
+            meta: vec![vec![" ".to_string(); 3]; 3],
+            next_board: None,
+            number_of_move: 0,
+            is_x: true,
+            over: false,
+        }
+    }
+
+    fn is_decided(&self, board: usize) -> bool {
+        self.meta[board / 3][board % 3] != " "
+    }
+
+    fn is_full(array: &[Vec<String>]) -> bool {
+        array.iter().all(|row| row.iter().all(|cell| cell != " "))
+    }
+}
+
+impl Game for UltimateXO {
+    //*
+    // Player input is the small board followed by the cell, both numbered
+    // from 1 to 9 like XO: 53 means cell 3 of the middle board
+    // */
+    fn make_a_move(&mut self, player_input: usize) -> Result<MoveOutcome, MoveError> {
+        if self.over {
+            return Err(MoveError::GameOver);
+        }
+        let board = player_input / 10;
+        let cell = player_input % 10;
+        if !(1..=9).contains(&board) || !(1..=9).contains(&cell) {
+            return Err(MoveError::OutOfRange);
+        }
+
+        //trim input to fit our calculation
+        let board = board - 1;
+        let cell = cell - 1;
+        if self.is_decided(board) || self.next_board.is_some_and(|next| next != board) {
+            return Err(MoveError::WrongBoard);
+        }
+        let row = cell / 3;
+        let col = cell % 3;
+        if self.boards[board][row][col] != " " {
+            return Err(MoveError::CellOccupied);
+        }
+
+        let mark = if self.is_x { "X" } else { "O" }.to_string();
+        self.is_x = !self.is_x;
+        self.boards[board][row][col] = mark.clone();
+        self.number_of_move += 1;
+
+        if XO::check(&self.boards[board], row, col, 3) {
+            self.meta[board / 3][board % 3] = mark;
+            if XO::check(&self.meta, board / 3, board % 3, 3) {
+                self.over = true;
+                //X is the first player that move, and here is_x flipped, so it should be !is_x
+                if !self.is_x {
+                    return Ok(MoveOutcome::Win(0));
+                } else {
+                    return Ok(MoveOutcome::Win(1));
+                }
+            }
+        } else if Self::is_full(&self.boards[board]) {
+            self.meta[board / 3][board % 3] = "-".to_string();
+        }
+
+        if Self::is_full(&self.meta) {
+            self.over = true;
+            return Ok(MoveOutcome::Draw);
+        }
+        self.next_board = if self.is_decided(cell) { None } else { Some(cell) };
+        Ok(MoveOutcome::Continue)
+    }
+
+    fn legal_moves(&self) -> Vec<usize> {
+        if self.over {
+            return Vec::new();
+        }
+        let boards = match self.next_board {
+            Some(next) => vec![next],
+            None => (0..9).filter(|board| !self.is_decided(*board)).collect()
+        };
+        let mut moves = Vec::new();
+        for board in boards {
+            for cell in 0..9 {
+                if self.boards[board][cell / 3][cell % 3] == " " {
+                    moves.push((board + 1) * 10 + cell + 1);
+                }
+            }
+        }
+        moves
+    }
+
+    fn reset(&self) -> Self {
+        UltimateXO::new()
+    }
+
+    //The 9x9 grid, small board b covers rows 3*(b/3).. and columns 3*(b%3)..
+    fn board(&self) -> Vec<Vec<String>> {
+        (0..9).map(|row| {
+            (0..9).map(|col| self.boards[row / 3 * 3 + col / 3][row % 3][col % 3].clone()).collect()
+        }).collect()
+    }
+
+    fn to_string(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+
+    fn from_string(board: &str) -> Self {
+        serde_json::from_str::<UltimateXO>(board).unwrap()
+    }
+
+    fn name(&self) -> String { "UltimateXO".to_string() }
+
+    fn meta_board(&self) -> Option<Vec<Vec<String>>> {
+        Some(self.meta.clone())
+    }
+
+    fn next_board(&self) -> Option<usize> {
+        if self.over { None } else { self.next_board.map(|board| board + 1) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::game::{Game, MoveError, MoveOutcome};
+    use crate::game::testing::play;
+    use super::UltimateXO;
+
+    #[test]
+    fn cell_played_sends_the_opponent_to_that_board() {
+        let mut game = UltimateXO::new();
+        assert_eq!(game.next_board(), None);
+        game.make_a_move(53).unwrap();
+        assert_eq!(game.next_board(), Some(3));
+        assert_eq!(game.make_a_move(41), Err(MoveError::WrongBoard));
+        assert!(game.legal_moves().iter().all(|input| input / 10 == 3));
+        game.make_a_move(31).unwrap();
+        assert_eq!(game.next_board(), Some(1));
+    }
+
+    #[test]
+    fn sent_to_a_decided_board_plays_anywhere() {
+        let mut game = UltimateXO::new();
+        //X wins board 1 with its top row, O keeps sending X back to it with cell 1
+        assert_eq!(play(&mut game, &[12, 21, 13, 31, 11]), MoveOutcome::Continue);
+        assert_eq!(game.meta_board().unwrap()[0][0], "X");
+        //cell 1 sends O to board 1, which is decided
+        assert_eq!(game.next_board(), None);
+        assert_eq!(game.make_a_move(15), Err(MoveError::WrongBoard));
+        assert!(game.legal_moves().iter().all(|input| input / 10 != 1));
+        game.make_a_move(95).unwrap();
+        assert_eq!(game.next_board(), Some(5));
+    }
+
+    #[test]
+    fn inputs_are_board_then_cell() {
+        let mut game = UltimateXO::new();
+        assert_eq!(game.make_a_move(5), Err(MoveError::OutOfRange));
+        assert_eq!(game.make_a_move(50), Err(MoveError::OutOfRange));
+        assert_eq!(game.make_a_move(100), Err(MoveError::OutOfRange));
+        game.make_a_move(55).unwrap();
+        assert_eq!(game.make_a_move(55), Err(MoveError::CellOccupied));
+        assert_eq!(game.board()[4][4], "X");
+    }
+
+    #[test]
+    fn three_small_boards_in_a_row_win() {
+        let mut game = UltimateXO::new();
+        //X wins board 1 then the middle row of boards 2 and 3, O only sends X where it needs to go
+        let outcome = play(&mut game, &[
+            12, 21, 13, 31, 11,
+            41, 25, 51, 24, 42, 26,
+            61, 35, 52, 34, 43,
+        ]);
+        assert_eq!(outcome, MoveOutcome::Continue);
+        assert_eq!(game.meta_board().unwrap()[1][0], "O");
+        assert_eq!(game.make_a_move(36), Ok(MoveOutcome::Win(0)));
+        assert_eq!(game.meta_board().unwrap()[0], vec!["X", "X", "X"]);
+        assert_eq!(game.next_board(), None);
+        assert!(game.legal_moves().is_empty());
+    }
+}
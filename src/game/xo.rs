@@ -1,139 +1,165 @@
-use std::str::FromStr;
 use serde::{Deserialize, Serialize};
-use crate::game::Game;
+use crate::game::{Game, MoveError, MoveOutcome};
 
 //TODO we need to standardize games so we can have multiple game implementation
 //A game module need player1 and player2 input stream, then someway to start, stop and get result
+//*
+// m,n,k game: a board of `rows` x `cols` cells where the first player to put `k` of their marks
+// in a row (horizontally, vertically or diagonally) wins. Classic XO is 3,3,3 and Gomoku is 15,15,5
+// */
 #[derive(Clone, Serialize, Deserialize)]
 pub struct XO {
-    board: [[String; 3]; 3],
+    board: Vec<Vec<String>>,
     number_of_move: i32,
     is_x: bool,
+    //Boards saved before m,n,k support don't have k, they are all 3x3
+    #[serde(default = "XO::default_k")]
+    k: usize,
+    #[serde(default)]
+    over: bool,
 }
 
 impl XO {
+    pub const MAX_SIZE: usize = 19;
+
     pub fn new() -> XO {
-        XO {
-            board: [[" ".to_string(), " ".to_string(), " ".to_string()],
-                [" ".to_string(), " ".to_string(), " ".to_string()],
-                [" ".to_string(), " ".to_string(), " ".to_string()]],
-            number_of_move: 0,
-            is_x: true,
-        }
+        Self::with_size(3, 3, 3)
     }
 
-    pub fn new_xo_for_scoreboard(board: [[String; 3]; 3]) -> Self {
+    pub fn with_size(rows: usize, cols: usize, k: usize) -> XO {
         XO {
-            board,
+            board: vec![vec![" ".to_string(); cols]; rows],
             number_of_move: 0,
             is_x: true,
+            k,
+            over: false,
         }
     }
 
-    fn check(array: &[[String; 3]; 3], row: usize, col: usize) -> bool {
-        let mut bool = true;
+    pub fn gomoku() -> XO {
+        Self::with_size(15, 15, 5)
+    }
 
-        //check top left to bottom right
-        if row == col {
-            //check diagonal
-            for i in 0..3 {
-                if array[i][i] != array[row][col] {
-                    bool = false;
-                    break;
-                }
-            }
-            if bool {
-                return true;
-            }
-        }
+    //*
+    // Check the size before creating a game from user input. Under 3 in a row the first player wins in
+    // a move or two, and a line has to fit across the board both ways or nobody may ever win
+    // */
+    pub fn is_valid_size(rows: usize, cols: usize, k: usize) -> bool {
+        rows <= Self::MAX_SIZE
+            && cols <= Self::MAX_SIZE
+            && k >= 3
+            && k <= rows.min(cols)
+    }
 
-        //check top right to bottom left
-        if row == 2 - col {
-            bool = true;
-            for i in 0..3 {
-                if array[i][2 - i] != array[row][col] {
-                    bool = false;
+    //(rows, cols, k)
+    pub fn size(&self) -> (usize, usize, usize) { (self.rows(), self.cols(), self.k) }
 
-                    break;
-                }
-            }
-            if bool {
-                return true;
-            }
-        }
+    fn default_k() -> usize { 3 }
 
-        //check column
-        bool = true;
-        for i in 0..3 {
-            if array[i][col] != array[row][col] {
-                bool = false;
-                break;
-            }
-        }
-        if bool {
-            return true;
+    fn rows(&self) -> usize { self.board.len() }
+
+    fn cols(&self) -> usize { self.board[0].len() }
+
+    //*
+    // Check if the mark at (row, col) is part of k marks in a row. Only the 4 lines going through
+    // (row, col) are scanned, so this stays cheap on big boards
+    // */
+    pub(crate) fn check(array: &[Vec<String>], row: usize, col: usize, k: usize) -> bool {
+        let mark = &array[row][col];
+        if mark == " " {
+            return false;
         }
 
-        //check row
-        bool = true;
-        for i in 0..3 {
-            if array[row][i] != array[row][col] {
-                bool = false;
-                break;
+        //horizontal, vertical, top left to bottom right, top right to bottom left
+        for (d_row, d_col) in [(0, 1), (1, 0), (1, 1), (1, -1)] {
+            let count = 1
+                + Self::count_direction(array, row, col, d_row, d_col, mark)
+                + Self::count_direction(array, row, col, -d_row, -d_col, mark);
+            if count >= k {
+                return true;
             }
         }
-        if bool {
-            return true;
-        }
         false
     }
+
+    //Count the same marks next to (row, col) going in one direction
+    fn count_direction(array: &[Vec<String>], row: usize, col: usize, d_row: isize, d_col: isize, mark: &str) -> usize {
+        let mut count = 0;
+        let mut row = row as isize + d_row;
+        let mut col = col as isize + d_col;
+        while row >= 0 && col >= 0
+            && (row as usize) < array.len()
+            && (col as usize) < array[row as usize].len()
+            && array[row as usize][col as usize] == mark {
+            count += 1;
+            row += d_row;
+            col += d_col;
+        }
+        count
+    }
 }
 
 impl Game for XO {
     //*
-    // Main function of the XO return 1 if player 1 win, 2 if player 2 win, 0 if nothing happened
-    // 3 if draw
+    // Main function of the XO. Cells are numbered from 1 to rows * cols, left to right then top to bottom
     // */
-    fn make_a_move(&mut self, player_input: usize) -> usize {
-        if self.number_of_move == 9 {
-            return 3;
+    fn make_a_move(&mut self, player_input: usize) -> Result<MoveOutcome, MoveError> {
+        if self.over {
+            return Err(MoveError::GameOver);
+        }
+        if player_input == 0 || player_input > self.rows() * self.cols() {
+            return Err(MoveError::OutOfRange);
         }
-
 
         //trim input to fit our calculation
         let trim = player_input - 1;
-        let row = trim / 3;
-        let col = trim % 3;
-        if self.board[row][col] == " " {
-            if self.is_x {
-                self.is_x = false;
-                self.board[row][col] = "X".to_string();
-            } else {
-                self.is_x = true;
-                self.board[row][col] = "O".to_string();
-            }
-            self.number_of_move += 1;
+        let row = trim / self.cols();
+        let col = trim % self.cols();
+        if self.board[row][col] != " " {
+            return Err(MoveError::CellOccupied);
+        }
+
+        if self.is_x {
+            self.is_x = false;
+            self.board[row][col] = "X".to_string();
+        } else {
+            self.is_x = true;
+            self.board[row][col] = "O".to_string();
         }
-        if Self::check(&self.board, row, col) {
+        self.number_of_move += 1;
+
+        if Self::check(&self.board, row, col, self.k) {
+            self.over = true;
             //X is the first player that move, and here is_x flipped, so it should be !is_x
             if !self.is_x {
-                return 1;
+                return Ok(MoveOutcome::Win(0));
             } else {
-                return 2;
+                return Ok(MoveOutcome::Win(1));
             }
         }
-        0
+        if self.number_of_move as usize == self.rows() * self.cols() {
+            self.over = true;
+            return Ok(MoveOutcome::Draw);
+        }
+        Ok(MoveOutcome::Continue)
     }
 
-    fn print(&self) -> String {
-        let mut board = String::new();
-        self.board.iter().for_each(|i| {
-            for j in i {
-                board.push_str(&*format!("{}|", j))
-            }
-            board.push_str("\n______\n");
-        });
-        board
+    fn legal_moves(&self) -> Vec<usize> {
+        if self.over {
+            return Vec::new();
+        }
+        (1..=self.rows() * self.cols())
+            .filter(|input| self.board[(input - 1) / self.cols()][(input - 1) % self.cols()] == " ")
+            .collect()
+    }
+
+    fn reset(&self) -> Self {
+        let (rows, cols, k) = self.size();
+        XO::with_size(rows, cols, k)
+    }
+
+    fn board(&self) -> Vec<Vec<String>> {
+        self.board.clone()
     }
 
     fn to_string(&self) -> String {
@@ -143,4 +169,117 @@ impl Game for XO {
     fn from_string(board: &str) -> Self {
         serde_json::from_str::<XO>(board).unwrap()
     }
-}
\ No newline at end of file
+
+    //Name of the preset, other sizes are written as m,n,k
+    fn name(&self) -> String {
+        match self.size() {
+            (3, 3, 3) => "XO".to_string(),
+            (15, 15, 5) => "Gomoku".to_string(),
+            (rows, cols, k) => format!("XO {},{},{}", rows, cols, k)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::game::{Game, MoveError, MoveOutcome};
+    use crate::game::testing::play;
+    use super::XO;
+
+    fn board(rows: &[&str]) -> Vec<Vec<String>> {
+        rows.iter().map(|row| row.chars().map(|cell| cell.to_string()).collect()).collect()
+    }
+
+    #[test]
+    fn check_counts_k_marks_in_every_direction() {
+        let array = board(&[
+            "XXXX ",
+            "O    ",
+            "OX   ",
+            "O X  ",
+            "O  X ",
+        ]);
+        //horizontal
+        assert!(XO::check(&array, 0, 2, 4));
+        assert!(!XO::check(&array, 0, 2, 5));
+        //vertical
+        assert!(XO::check(&array, 3, 0, 4));
+        assert!(!XO::check(&array, 3, 0, 5));
+        //diagonal, only 3 marks from (2, 1) to (4, 3)
+        assert!(XO::check(&array, 3, 2, 3));
+        assert!(!XO::check(&array, 3, 2, 4));
+        //empty cells are never part of a line
+        assert!(!XO::check(&array, 0, 4, 1));
+    }
+
+    #[test]
+    fn check_counts_the_anti_diagonal() {
+        let array = board(&[
+            "   X",
+            "  X ",
+            " X  ",
+            "X   ",
+        ]);
+        assert!(XO::check(&array, 1, 2, 4));
+    }
+
+    #[test]
+    fn gomoku_needs_five_in_a_row() {
+        let mut game = XO::gomoku();
+        //X plays 1 to 4 on the first row, O answers on the second
+        assert_eq!(play(&mut game, &[1, 16, 2, 17, 3, 18, 4, 19]), MoveOutcome::Continue);
+        assert_eq!(game.make_a_move(5), Ok(MoveOutcome::Win(0)));
+        assert_eq!(game.make_a_move(6), Err(MoveError::GameOver));
+        assert!(game.legal_moves().is_empty());
+    }
+
+    #[test]
+    fn second_player_wins_on_a_rectangular_board() {
+        //4 rows of 5, O makes 4 in a column
+        let mut game = XO::with_size(4, 5, 4);
+        assert_eq!(play(&mut game, &[1, 5, 2, 10, 3, 15]), MoveOutcome::Continue);
+        assert_eq!(play(&mut game, &[7, 20]), MoveOutcome::Win(1));
+    }
+
+    #[test]
+    fn full_board_without_a_line_is_a_draw() {
+        let mut game = XO::new();
+        assert_eq!(play(&mut game, &[1, 2, 3, 5, 4, 6, 8, 7, 9]), MoveOutcome::Draw);
+    }
+
+    #[test]
+    fn invalid_moves_leave_the_game_unchanged() {
+        let mut game = XO::with_size(3, 4, 3);
+        assert_eq!(game.make_a_move(0), Err(MoveError::OutOfRange));
+        assert_eq!(game.make_a_move(13), Err(MoveError::OutOfRange));
+        game.make_a_move(12).unwrap();
+        assert_eq!(game.make_a_move(12), Err(MoveError::CellOccupied));
+        assert_eq!(game.legal_moves(), (1..12).collect::<Vec<usize>>());
+        assert_eq!(game.board()[2][3], "X");
+    }
+
+    #[test]
+    fn boards_saved_before_k_are_3x3() {
+        let game = XO::from_string(r#"{"board":[["X"," "," "],[" "," "," "],[" "," "," "]],"number_of_move":1,"is_x":false}"#);
+        assert_eq!(game.size(), (3, 3, 3));
+        assert_eq!(game.name(), "XO");
+    }
+
+    #[test]
+    fn valid_sizes() {
+        assert!(XO::is_valid_size(3, 3, 3));
+        assert!(XO::is_valid_size(4, 5, 4));
+        assert!(XO::is_valid_size(XO::MAX_SIZE, XO::MAX_SIZE, 5));
+        //nobody can ever win
+        assert!(!XO::is_valid_size(1, 5, 5));
+        assert!(!XO::is_valid_size(4, 5, 5));
+        //the first move wins
+        assert!(!XO::is_valid_size(3, 3, 1));
+        assert!(!XO::is_valid_size(3, 3, 2));
+        assert!(!XO::is_valid_size(0, 3, 3));
+        assert!(!XO::is_valid_size(XO::MAX_SIZE + 1, 3, 3));
+        assert!(!XO::is_valid_size(3, 3, 4));
+        assert!(!XO::is_valid_size(3, 3, 0));
+        assert_eq!(XO::with_size(4, 5, 4).name(), "XO 4,5,4");
+    }
+}
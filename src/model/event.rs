@@ -0,0 +1,21 @@
+use serde::Serialize;
+use crate::model::view::SessionStatus;
+
+//*
+// Something that happened in a session, pushed to every client listening to it
+// */
+#[derive(Clone, Serialize, Debug)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum SessionEvent {
+    OpponentJoined { username: String },
+    MoveMade { player: usize, player_input: usize, board: Vec<Vec<String>> },
+    TakebackRequested { player: usize },
+    //Player is the one who asked for the takeback
+    TakebackDeclined { player: usize },
+    TakenBack { player: usize, board: Vec<Vec<String>> },
+    Surrender { player: usize },
+    GameEnded { status: SessionStatus, winner: Option<usize>, board: Vec<Vec<String>> },
+    //The challenge is gone, the session is closed right after
+    ChallengeDeclined { username: String },
+    ChallengeExpired,
+}
@@ -0,0 +1,171 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Notify;
+use tokio::time::Instant;
+use crate::game::{AnyGame, Game};
+use crate::model::session::SessionID;
+
+//*
+// A player waiting for an opponent. Players are only paired with someone who asked for the same game
+// with the same options
+// */
+pub struct QueueEntry {
+    pub username: String,
+    //Name of the game, the same for every request that ends up with the same board whatever options it set
+    pub game_key: String,
+    pub game: AnyGame,
+    //Last time the player enqueued or asked how it's going
+    last_seen: Instant,
+}
+
+impl QueueEntry {
+    pub fn new(username: String, game: AnyGame) -> Self {
+        QueueEntry {
+            username,
+            game_key: game.name(),
+            game,
+            last_seen: Instant::now(),
+        }
+    }
+}
+
+//*
+// Players waiting for an opponent, oldest first, and the sessions made for players who were waiting
+// until they pick them up. Pairing is first come first served, players have no rating yet to pair
+// them by
+// */
+#[derive(Default)]
+pub struct Matchmaking {
+    queue: VecDeque<QueueEntry>,
+    //Session made for each paired player and when it was made
+    matched: HashMap<String, (SessionID, Instant)>,
+    //Wakes up every request long-polling the queue
+    changed: Arc<Notify>,
+}
+
+impl Matchmaking {
+    //A player who stopped polling for this long left without saying, nobody should be paired with them
+    const STALE_AFTER: Duration = Duration::from_secs(2 * 60);
+
+    //*
+    // Put the player in the queue, replacing their previous entry. If someone is already waiting for
+    // the same game they are taken out of the queue and returned to be paired with the player
+    // */
+    pub fn enqueue(&mut self, entry: QueueEntry) -> Option<QueueEntry> {
+        let now = Instant::now();
+        self.queue.retain(|waiting| waiting.username != entry.username && now - waiting.last_seen < Self::STALE_AFTER);
+        //Paired players who never came to pick up their session
+        self.matched.retain(|_, (_, matched_at)| now - *matched_at < Self::STALE_AFTER);
+        self.matched.remove(&entry.username);
+        match self.queue.iter().position(|waiting| waiting.game_key == entry.game_key) {
+            Some(index) => self.queue.remove(index),
+            None => {
+                self.queue.push_back(entry);
+                None
+            }
+        }
+    }
+
+    //false if the player wasn't in the queue
+    pub fn leave(&mut self, username: &str) -> bool {
+        let length = self.queue.len();
+        self.queue.retain(|waiting| waiting.username != username);
+        self.queue.len() != length
+    }
+
+    //Position in the queue starting from 1, the player is seen so their entry isn't stale
+    pub fn touch(&mut self, username: &str) -> Option<usize> {
+        let index = self.queue.iter().position(|waiting| waiting.username == username)?;
+        self.queue[index].last_seen = Instant::now();
+        Some(index + 1)
+    }
+
+    //Session made for the waiting player, they get it once
+    pub fn take_match(&mut self, username: &str) -> Option<SessionID> {
+        self.matched.remove(username).map(|(session_id, _)| session_id)
+    }
+
+    pub fn add_match(&mut self, username: String, session_id: SessionID) {
+        self.matched.insert(username, (session_id, Instant::now()));
+        self.changed.notify_waiters();
+    }
+
+    pub fn watch(&self) -> Arc<Notify> {
+        self.changed.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::time::Instant;
+    use crate::game::AnyGame;
+    use crate::game::connect_four::ConnectFour;
+    use crate::game::xo::XO;
+    use crate::model::session::SessionID;
+    use super::{Matchmaking, QueueEntry};
+
+    fn xo(username: &str) -> QueueEntry {
+        QueueEntry::new(username.to_string(), AnyGame::XO(XO::new()))
+    }
+
+    fn long_ago() -> Instant {
+        Instant::now().checked_sub(Matchmaking::STALE_AFTER).unwrap()
+    }
+
+    #[test]
+    fn first_come_first_served() {
+        let mut matchmaking = Matchmaking::default();
+        assert!(matchmaking.enqueue(xo("alice")).is_none());
+        assert!(matchmaking.enqueue(xo("bob")).is_some_and(|entry| entry.username == "alice"));
+        assert!(matchmaking.enqueue(xo("carol")).is_none());
+        assert!(matchmaking.enqueue(xo("dave")).is_some_and(|entry| entry.username == "carol"));
+        assert_eq!(matchmaking.touch("carol"), None);
+    }
+
+    #[test]
+    fn only_paired_for_the_same_game() {
+        let mut matchmaking = Matchmaking::default();
+        matchmaking.enqueue(QueueEntry::new("alice".to_string(), AnyGame::ConnectFour(ConnectFour::new())));
+        assert!(matchmaking.enqueue(QueueEntry::new("bob".to_string(), AnyGame::XO(XO::gomoku()))).is_none());
+        //3x3 XO asked with its size is still classic XO
+        assert!(matchmaking.enqueue(xo("carol")).is_none());
+        let entry = matchmaking.enqueue(QueueEntry::new("dave".to_string(), AnyGame::XO(XO::with_size(3, 3, 3)))).unwrap();
+        assert_eq!(entry.username, "carol");
+        assert_eq!(matchmaking.touch("alice"), Some(1));
+        assert_eq!(matchmaking.touch("bob"), Some(2));
+    }
+
+    #[test]
+    fn enqueue_again_replaces_the_entry() {
+        let mut matchmaking = Matchmaking::default();
+        matchmaking.enqueue(xo("alice"));
+        assert!(matchmaking.enqueue(xo("alice")).is_none());
+        assert_eq!(matchmaking.touch("alice"), Some(1));
+        assert!(matchmaking.leave("alice"));
+        assert!(!matchmaking.leave("alice"));
+    }
+
+    #[test]
+    fn stale_players_are_not_paired() {
+        let mut matchmaking = Matchmaking::default();
+        matchmaking.enqueue(xo("alice"));
+        matchmaking.enqueue(QueueEntry::new("bob".to_string(), AnyGame::ConnectFour(ConnectFour::new())));
+        matchmaking.queue[0].last_seen = long_ago();
+        assert!(matchmaking.enqueue(xo("carol")).is_none());
+        assert_eq!(matchmaking.touch("alice"), None);
+        assert_eq!(matchmaking.touch("bob"), Some(1));
+    }
+
+    #[test]
+    fn matches_nobody_picked_up_are_dropped() {
+        let mut matchmaking = Matchmaking::default();
+        matchmaking.add_match("alice".to_string(), SessionID("1".to_string()));
+        matchmaking.add_match("bob".to_string(), SessionID("2".to_string()));
+        matchmaking.matched.get_mut("alice").unwrap().1 = long_ago();
+        matchmaking.enqueue(xo("carol"));
+        assert_eq!(matchmaking.take_match("alice"), None);
+        assert_eq!(matchmaking.take_match("bob"), Some(SessionID("2".to_string())));
+        assert_eq!(matchmaking.take_match("bob"), None);
+    }
+}
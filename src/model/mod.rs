@@ -1,3 +1,5 @@
-pub mod multithread_session;
+pub mod event;
+pub mod matchmaking;
 pub mod player;
-pub mod session;
\ No newline at end of file
+pub mod session;
+pub mod view;
\ No newline at end of file
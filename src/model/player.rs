@@ -1,3 +1,5 @@
+use rand::distributions::Alphanumeric;
+use rand::Rng;
 use serde::Deserialize;
 use crate::model::session::SessionID;
 
@@ -9,6 +11,11 @@ pub struct Player {
 }
 
 impl Player {
+    //Name left on the saved sessions of a deleted account
+    pub const DELETED_USERNAME: &'static str = "[deleted]";
+    //Generated usernames of guests start with it, nobody else can take one
+    pub const GUEST_PREFIX: &'static str = "guest-";
+
     pub(crate) fn new(username: String, password: String) -> Player {
         Player {
             username,
@@ -17,6 +24,17 @@ impl Player {
         }
     }
 
+    //*
+    // Guest with a random name and a random password nobody knows, the guest only ever logs in
+    // with the token from guest login
+    // */
+    pub fn new_guest() -> Player {
+        let mut rng = rand::thread_rng();
+        let name: String = (0..8).map(|_| rng.sample(Alphanumeric).to_ascii_lowercase() as char).collect();
+        let password: String = (0..32).map(|_| rng.sample(Alphanumeric) as char).collect();
+        Player::new(format!("{}{}", Self::GUEST_PREFIX, name), password)
+    }
+
     pub fn set_session_id(&mut self, session_id: SessionID) {
         self.session_id = Some(session_id)
     }
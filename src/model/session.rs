@@ -1,81 +1,588 @@
 use std::collections::HashMap;
-use std::hash::{DefaultHasher, Hash, Hasher};
-use std::time::SystemTime;
-use serde::{Deserialize, Serialize, Serializer};
-use tokio::sync::{mpsc};
-use warp::http::StatusCode;
-use crate::dao::{DAO, Database};
-use crate::error::Error;
-use crate::game::Game;
-use crate::game::xo::XO;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{broadcast, Notify, RwLock};
+use crate::ai::{Bot, Difficulty};
+use crate::game::{AnyGame, Game, MoveError, MoveOutcome};
+use crate::model::event::SessionEvent;
 use crate::model::player::Player;
+use crate::model::view::SessionStatus;
 
-#[derive(Clone, Deserialize)]
+#[derive(Clone)]
 pub struct Session<T> where T: Game + Clone {
     session_id: SessionID,
+    //Short code to share instead of the session id, None for saved sessions
+    join_code: Option<String>,
+    //Left out of the lobby, joined with the join code or by an invited player only
+    pub private: bool,
+    pub invited: Vec<String>,
+    //Pending challenge to a named player, cleared once they accept
+    pub challenge: Option<Challenge>,
     pub players: [Option<Player>; 2],
     pub game: T,
     pub turn: usize,
     pub end: bool,
-    pub status: usize
+    pub status: MoveOutcome,
+    //Computer player sitting in players[1]
+    pub bot: Option<Bot>,
+    //Every move played so far, in order
+    pub moves: Vec<Move>,
+    //Index of the player waiting for the opponent to accept a takeback
+    pub takeback_request: Option<usize>,
+    events: broadcast::Sender<(u64, SessionEvent)>,
+    //Every event sent so far, the id of an event is its position in the log starting from 1
+    event_log: Vec<SessionEvent>,
+    //Wakes up every request long-polling for a change of this session
+    changed: Arc<Notify>
 }
 
 #[derive(Eq, PartialEq, Hash, Clone, Serialize, Deserialize, Debug)]
 pub struct SessionID(pub String);
 
+//Every session being played, shared by the routes
+pub type SessionList = Arc<RwLock<Sessions>>;
+
+//*
+// The sessions being played and the join codes they are found by. Sessions are only added, removed and
+// given a join code through it so the codes always point to the right session
+// */
+#[derive(Default)]
+pub struct Sessions {
+    sessions: HashMap<SessionID, RwLock<Session<AnyGame>>>,
+    //Upper case join code to the session it opens
+    join_codes: HashMap<String, SessionID>,
+}
+
+impl Sessions {
+    pub fn get(&self, session_id: &SessionID) -> Option<&RwLock<Session<AnyGame>>> {
+        self.sessions.get(session_id)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&SessionID, &RwLock<Session<AnyGame>>)> {
+        self.sessions.iter()
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &RwLock<Session<AnyGame>>> {
+        self.sessions.values()
+    }
+
+    //*
+    // Add the session with new ids if the ones it has are taken, the session keeps the ids it was
+    // added with
+    // */
+    pub fn insert(&mut self, session: &mut Session<AnyGame>) {
+        while self.sessions.contains_key(&session.session_id)
+            || session.join_code().is_some_and(|code| self.find_by_join_code(code).is_some()) {
+            session.regenerate_ids();
+        }
+        if let Some(code) = session.join_code() {
+            self.join_codes.insert(code.to_uppercase(), session.session_id.clone());
+        }
+        self.sessions.insert(session.session_id.clone(), RwLock::new(session.clone()));
+    }
+
+    pub fn remove(&mut self, session_id: &SessionID) -> Option<RwLock<Session<AnyGame>>> {
+        let mut session = self.sessions.remove(session_id)?;
+        if let Some(code) = session.get_mut().join_code() {
+            self.join_codes.remove(&code.to_uppercase());
+        }
+        Some(session)
+    }
+
+    //Codes are case insensitive
+    pub fn find_by_join_code(&self, code: &str) -> Option<&SessionID> {
+        self.join_codes.get(&code.trim().to_uppercase())
+    }
+
+    //A code no active session uses
+    pub fn unused_join_code(&self) -> String {
+        loop {
+            let code = Session::<AnyGame>::generate_join_code();
+            if self.find_by_join_code(&code).is_none() {
+                return code;
+            }
+        }
+    }
+
+    //None revokes the code of the session, only invited players can join then
+    pub fn set_join_code(&mut self, session_id: &SessionID, join_code: Option<String>) {
+        let Some(session) = self.sessions.get_mut(session_id) else { return };
+        let session = session.get_mut();
+        if let Some(code) = session.join_code() {
+            self.join_codes.remove(&code.to_uppercase());
+        }
+        if let Some(code) = &join_code {
+            self.join_codes.insert(code.to_uppercase(), session_id.clone());
+        }
+        session.join_code = join_code;
+    }
+}
+
+#[derive(Clone, Serialize, Debug)]
+pub struct Challenge {
+    pub opponent: String,
+    //Seconds since epoch
+    pub expires_at: u64,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct Move {
+    //Index of the player in Session::players
+    pub player: usize,
+    pub player_input: usize,
+    //Milliseconds since epoch
+    pub played_at: i64,
+}
+
 //impl<T: Game + Sized + Clone + Send>
 impl<T: Game + Clone> Session<T> {
+    //Events a slow listener can fall behind before missing some
+    const EVENT_CAPACITY: usize = 16;
+    //No 0, O, 1 or I so a code read aloud or off a screen can't be mistyped
+    const JOIN_CODE_ALPHABET: &'static [u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+    const JOIN_CODE_LENGTH: usize = 6;
+
     pub fn new(mut player: Player, game: T) -> Self {
         let session_id = Self::generate_session_id();
         player.set_session_id(session_id.clone());
         Session {
             session_id,
+            join_code: Some(Self::generate_join_code()),
+            private: false,
+            invited: Vec::new(),
+            challenge: None,
             players: [Some(player), None],
             game,
             turn: 0,
             end: false,
-            status: 0
+            status: MoveOutcome::Continue,
+            bot: None,
+            moves: Vec::new(),
+            takeback_request: None,
+            events: broadcast::channel(Self::EVENT_CAPACITY).0,
+            event_log: Vec::new(),
+            changed: Arc::new(Notify::new())
         }
     }
 
-    pub fn new_session_for_scoreboard(session_id: i32, players: [Option<Player>; 2], status: String, board: String) -> Self {
+    pub fn new_session_for_scoreboard(session_id: i32, players: [Option<Player>; 2], status: String, board: String, bot: Option<String>) -> Self {
         Session {
             session_id: SessionID(session_id.to_string()),
+            join_code: None,
+            private: false,
+            invited: Vec::new(),
+            challenge: None,
             players,
             game: T::from_string(board.as_str()),
             turn: 0,
             end: true,
-            status: status.parse::<usize>().unwrap()
+            status: MoveOutcome::from_code(status.parse::<i32>().unwrap()),
+            bot: bot.and_then(|difficulty| difficulty.parse::<Difficulty>().ok()).map(Bot::new),
+            moves: Vec::new(),
+            takeback_request: None,
+            events: broadcast::channel(Self::EVENT_CAPACITY).0,
+            event_log: Vec::new(),
+            changed: Arc::new(Notify::new())
         }
     }
 
+    //128 random bits from a cryptographically secure generator, nobody can guess the session of someone else
     fn generate_session_id() -> SessionID {
-        let mut hasher = DefaultHasher::new();
-        SystemTime::now().hash(&mut hasher);
-        SessionID(hasher.finish().to_string())
+        SessionID(format!("{:032x}", rand::thread_rng().gen::<u128>()))
+    }
+
+    pub fn generate_join_code() -> String {
+        let mut rng = rand::thread_rng();
+        (0..Self::JOIN_CODE_LENGTH)
+            .map(|_| Self::JOIN_CODE_ALPHABET[rng.gen_range(0..Self::JOIN_CODE_ALPHABET.len())] as char)
+            .collect()
+    }
+
+    //*
+    // New session id and join code, for when the ones generated are already used by an active session.
+    // Only before the session is shared, the players keep the id they were given. A session without a
+    // join code, like a challenge, stays without one
+    // */
+    pub fn regenerate_ids(&mut self) {
+        self.session_id = Self::generate_session_id();
+        self.join_code = self.join_code.as_ref().map(|_| Self::generate_join_code());
+        let session_id = self.session_id.clone();
+        for player in self.players.iter_mut().flatten() {
+            player.set_session_id(session_id.clone());
+        }
+    }
+
+    pub fn join_code(&self) -> Option<&str> { self.join_code.as_deref() }
+
+    pub fn is_creator(&self, player: &Player) -> bool {
+        self.player_index(player) == Some(0)
+    }
+
+    //*
+    // Addressed to the opponent only: private, they are the only one invited and there is no join code
+    // to pass around
+    // */
+    pub fn challenge(&mut self, opponent: String, expires_at: u64) {
+        self.private = true;
+        self.invited = vec![opponent.clone()];
+        self.join_code = None;
+        self.challenge = Some(Challenge { opponent, expires_at });
+    }
+
+    pub fn is_challenge_expired(&self) -> bool {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+        self.challenge.as_ref().is_some_and(|challenge| challenge.expires_at <= now)
+    }
+
+    //Anyone can join a public session, a private one needs the join code or an invitation
+    pub fn is_invited(&self, player: &Player) -> bool {
+        !self.private || self.invited.contains(&player.get_username())
+    }
+
+    //Seat of the player in this session, players are told apart by their username
+    pub fn player_index(&self, player: &Player) -> Option<usize> {
+        self.players.iter().position(|p| p.as_ref().map(|p| p.get_username()) == Some(player.get_username()))
+    }
+
+    //*
+    // The player changed their username or deleted their account, Player::DELETED_USERNAME then. Their seat,
+    // invitation and challenge follow them. Nobody can log in as a deleted account so its invitation is
+    // dropped and a challenge from or to it expires right away
+    // */
+    pub fn rename_player(&mut self, username: &str, new_username: &str) {
+        let seat = self.player_index(&Player::new(username.to_string(), String::new()));
+        if let Some(index) = seat {
+            let mut player = Player::new(new_username.to_string(), String::new());
+            player.set_session_id(self.session_id.clone());
+            self.players[index] = Some(player);
+        }
+        if let Some(challenge) = self.challenge.as_mut() {
+            if new_username == Player::DELETED_USERNAME && (seat.is_some() || challenge.opponent == username) {
+                challenge.expires_at = 0;
+            } else if challenge.opponent == username {
+                challenge.opponent = new_username.to_string();
+            }
+        }
+        if new_username == Player::DELETED_USERNAME {
+            self.invited.retain(|invited| invited != username);
+        } else {
+            self.invited.iter_mut()
+                .filter(|invited| *invited == username)
+                .for_each(|invited| *invited = new_username.to_string());
+        }
     }
 
     pub fn can_join(&self) -> bool {
-        !self.players[1].is_some()
+        self.players[1].is_none()
     }
 
     pub fn add_player2(&mut self, mut player2: Player) {
         player2.set_session_id(self.session_id.clone());
+        self.notify(SessionEvent::OpponentJoined { username: player2.get_username() });
         self.players[1] = Some(player2);
     }
 
+    //Listen to everything happening in this session from now on, events come with their id
+    pub fn subscribe(&self) -> broadcast::Receiver<(u64, SessionEvent)> {
+        self.events.subscribe()
+    }
+
+    //Id of the last event sent, 0 before the first one
+    pub fn last_event_id(&self) -> u64 {
+        self.event_log.len() as u64
+    }
+
+    //Events after last_event_id, for clients coming back after losing the connection
+    pub fn events_since(&self, last_event_id: u64) -> Vec<(u64, SessionEvent)> {
+        self.event_log.iter()
+            .enumerate()
+            .skip(last_event_id as usize)
+            .map(|(index, event)| (index as u64 + 1, event.clone()))
+            .collect()
+    }
+
+    //*
+    // Get notified of the next change. Call notified() on it before releasing the session lock,
+    // the change can only happen under the write lock so it can't be missed
+    // */
+    pub fn watch(&self) -> Arc<Notify> {
+        self.changed.clone()
+    }
+
+    pub fn notify(&mut self, event: SessionEvent) {
+        self.event_log.push(event.clone());
+        //Nobody listening is fine
+        let _ = self.events.send((self.event_log.len() as u64, event));
+        self.changed.notify_waiters();
+    }
+
+    fn notify_game_ended(&mut self) {
+        self.notify(SessionEvent::GameEnded {
+            status: SessionStatus::new(self), winner: self.status.winner(), board: self.game.board()
+        });
+    }
+
+    pub fn add_bot(&mut self, bot: Bot) {
+        self.add_player2(Bot::player());
+        self.bot = Some(bot);
+    }
+
+    //*
+    // Play the input for the player whose turn it is, then give the turn to the other player
+    // */
+    pub fn play(&mut self, player_input: usize) -> Result<MoveOutcome, MoveError> {
+        if self.end {
+            return Err(MoveError::GameOver);
+        }
+        let status = self.game.make_a_move(player_input)?;
+        let played_at = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as i64;
+        self.moves.push(Move { player: self.turn, player_input, played_at });
+        self.takeback_request = None;
+        self.status = status;
+        self.notify(SessionEvent::MoveMade { player: self.turn, player_input, board: self.game.board() });
+        self.turn = (self.turn + 1) % 2;
+        if status.is_over() {
+            self.end = true;
+            self.notify_game_ended();
+        }
+        Ok(status)
+    }
+
+    //The other player wins
+    pub fn surrender(&mut self, player: usize) {
+        self.end = true;
+        self.status = MoveOutcome::Win((player + 1) % 2);
+        self.notify(SessionEvent::Surrender { player });
+        self.notify_game_ended();
+    }
+
+    //*
+    // The bot and a copy of the game when it's the bot's turn, so the search can run without holding
+    // the session. The version tells play_bot whether the session changed in the meantime
+    // */
+    pub fn bot_turn(&self) -> Option<(Bot, T, usize)> {
+        match &self.bot {
+            Some(bot) if self.turn == 1 && !self.end => Some((bot.clone(), self.game.clone(), self.event_log.len())),
+            _ => None
+        }
+    }
+
+    //*
+    // Play the move the bot found for the given version of the session. Every change sends an event, so if
+    // there is a new one the move was searched for a position that is gone and it's dropped
+    // */
+    pub fn play_bot(&mut self, version: usize, player_input: usize) -> Result<MoveOutcome, MoveError> {
+        if self.event_log.len() != version || self.turn != 1 || self.end {
+            return Ok(self.status);
+        }
+        self.play(player_input)
+    }
+
+    //*
+    // Take back the last moves of the player, and the opponent reply if they already played.
+    // The game is replayed from the start without those moves
+    // */
+    pub fn take_back(&mut self, player: usize) -> bool {
+        let plies = match self.moves.iter().rev().position(|m| m.player == player) {
+            Some(plies) => plies + 1,
+            None => return false
+        };
+        self.moves.truncate(self.moves.len() - plies);
+        self.game = self.game.reset();
+        for m in &self.moves {
+            self.game.make_a_move(m.player_input).expect("Moves in the history were already accepted once");
+        }
+        self.turn = player;
+        self.status = MoveOutcome::Continue;
+        self.takeback_request = None;
+        self.notify(SessionEvent::TakenBack { player, board: self.game.board() });
+        true
+    }
+
     pub fn get_session_id(&self) -> SessionID { self.session_id.clone() }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::game::{AnyGame, Game, MoveOutcome};
+    use crate::game::xo::XO;
+    use crate::model::event::SessionEvent;
+    use crate::model::player::Player;
+    use super::{Session, Sessions};
+
+    fn session(inputs: &[usize]) -> Session<XO> {
+        let mut session = Session::new(Player::new("alice".to_string(), String::new()), XO::new());
+        session.add_player2(Player::new("bob".to_string(), String::new()));
+        for input in inputs {
+            session.play(*input).unwrap();
+        }
+        session
+    }
+
+    #[test]
+    fn take_back_removes_the_reply_of_the_opponent_too() {
+        let mut session = session(&[5, 1, 9]);
+        assert!(session.take_back(1));
+        assert_eq!(session.moves.iter().map(|m| m.player_input).collect::<Vec<usize>>(), vec![5]);
+        assert_eq!(session.turn, 1);
+        assert_eq!(session.game.board()[0][0], " ");
+        assert_eq!(session.game.board()[2][2], " ");
+        //the game accepts the cells back
+        session.play(1).unwrap();
+        assert_eq!(session.game.board()[0][0], "O");
+    }
+
+    #[test]
+    fn take_back_of_the_last_move() {
+        let mut session = session(&[5, 1]);
+        assert!(session.take_back(1));
+        assert_eq!(session.moves.len(), 1);
+        assert_eq!(session.turn, 1);
+        assert_eq!(session.game.legal_moves().len(), 8);
+    }
+
+    #[test]
+    fn take_back_needs_a_move_to_take_back() {
+        let mut session = session(&[5]);
+        assert!(!session.take_back(1));
+        assert_eq!(session.moves.len(), 1);
+    }
+
+    #[test]
+    fn moves_are_replayed_with_the_right_marks() {
+        let mut session = session(&[1, 4, 2, 5]);
+        session.takeback_request = Some(0);
+        assert!(session.take_back(0));
+        assert_eq!(session.takeback_request, None);
+        //X on 1, O on 4, X to play again and win the row later
+        assert_eq!(session.turn, 0);
+        for input in [2, 7, 3] {
+            session.play(input).unwrap();
+        }
+        assert_eq!(session.status, MoveOutcome::Win(0));
+        assert!(session.end);
+    }
+
+    #[test]
+    fn events_since_replays_what_came_after() {
+        let mut session = session(&[]);
+        assert_eq!(session.last_event_id(), 1);
+        let mut receiver = session.subscribe();
+        session.play(5).unwrap();
+        session.play(1).unwrap();
+        assert_eq!(session.last_event_id(), 3);
+
+        let missed = session.events_since(1);
+        assert_eq!(missed.iter().map(|(id, _)| *id).collect::<Vec<u64>>(), vec![2, 3]);
+        assert!(matches!(missed[1].1, SessionEvent::MoveMade { player: 1, player_input: 1, .. }));
+        //the ids are the ones the listeners got
+        assert_eq!(receiver.try_recv().unwrap().0, 2);
+        assert_eq!(receiver.try_recv().unwrap().0, 3);
+
+        assert_eq!(session.events_since(0).len(), 3);
+        assert!(session.events_since(3).is_empty());
+        assert!(session.events_since(10).is_empty());
+    }
+
+    fn new_session() -> Session<AnyGame> {
+        Session::new(Player::new("alice".to_string(), String::new()), AnyGame::XO(XO::new()))
+    }
+
+    #[test]
+    fn taken_ids_are_regenerated_on_insert() {
+        let mut sessions = Sessions::default();
+        let session = new_session();
+        sessions.insert(&mut session.clone());
 
-    pub fn get_player1_name(&self) -> String { self.players[1].clone().unwrap().get_username() }
+        let mut same_id = session.clone();
+        same_id.join_code = Some("AAAAAA".to_string());
+        sessions.insert(&mut same_id);
+        assert_ne!(same_id.session_id, session.session_id);
+        assert_eq!(sessions.find_by_join_code(same_id.join_code().unwrap()), Some(&same_id.session_id));
 
-    pub fn print_status(&self) -> String {
-        match self.status {
-            1 => "Player 1 win the game",
-            2 => "Player 2 win the game",
-            3 => "DRAW!",
-            _ => ""
-        }.to_string()
+        let mut same_code = new_session();
+        same_code.join_code = session.join_code.as_ref().map(|code| code.to_lowercase());
+        sessions.insert(&mut same_code);
+        assert_ne!(same_code.join_code().unwrap().to_uppercase(), session.join_code().unwrap());
+        assert_eq!(sessions.find_by_join_code(session.join_code().unwrap()), Some(&session.session_id));
+        assert_eq!(sessions.iter().count(), 3);
+        //the players follow the session to its new id
+        assert_eq!(same_code.players[0].as_ref().unwrap().session_id, Some(same_code.session_id.clone()));
     }
 
-    pub fn end(&mut self) {self.end = true;}
-}
\ No newline at end of file
+    #[test]
+    fn join_codes_are_found_whatever_the_case() {
+        let mut sessions = Sessions::default();
+        let mut session = new_session();
+        sessions.insert(&mut session);
+        let code = session.join_code().unwrap().to_string();
+        assert_eq!(sessions.find_by_join_code(&code.to_lowercase()), Some(&session.session_id));
+        assert_eq!(sessions.find_by_join_code(&format!(" {} ", code)), Some(&session.session_id));
+        assert_eq!(sessions.find_by_join_code("AAAAA"), None);
+    }
+
+    #[test]
+    fn removed_and_revoked_codes_leave_the_index() {
+        let mut sessions = Sessions::default();
+        let mut removed = new_session();
+        sessions.insert(&mut removed);
+        assert!(sessions.remove(&removed.session_id).is_some());
+        assert_eq!(sessions.find_by_join_code(removed.join_code().unwrap()), None);
+        assert!(sessions.remove(&removed.session_id).is_none());
+
+        let mut revoked = new_session();
+        sessions.insert(&mut revoked);
+        let old_code = revoked.join_code().unwrap().to_string();
+        sessions.set_join_code(&revoked.session_id, Some("BBBBBB".to_string()));
+        assert_eq!(sessions.find_by_join_code(&old_code), None);
+        assert_eq!(sessions.find_by_join_code("bbbbbb"), Some(&revoked.session_id));
+        sessions.set_join_code(&revoked.session_id, None);
+        assert_eq!(sessions.find_by_join_code("BBBBBB"), None);
+        assert!(sessions.join_codes.is_empty());
+        assert_eq!(sessions.get(&revoked.session_id).unwrap().try_read().unwrap().join_code(), None);
+    }
+
+    #[test]
+    fn challenges_keep_no_join_code_with_new_ids() {
+        let mut session = new_session();
+        session.challenge("bob".to_string(), u64::MAX);
+        let mut sessions = Sessions::default();
+        sessions.insert(&mut session.clone());
+        //same id, it has to be regenerated
+        let mut copy = session.clone();
+        sessions.insert(&mut copy);
+        assert_ne!(copy.session_id, session.session_id);
+        assert_eq!(copy.join_code(), None);
+        assert!(sessions.join_codes.is_empty());
+    }
+
+    //alice challenged bob
+    fn challenge() -> Session<AnyGame> {
+        let mut session = new_session();
+        session.challenge("bob".to_string(), u64::MAX);
+        session
+    }
+
+    #[test]
+    fn deleted_challenger_or_opponent_expires_the_challenge() {
+        for deleted in ["alice", "bob"] {
+            let mut session = challenge();
+            assert!(!session.is_challenge_expired());
+            session.rename_player(deleted, Player::DELETED_USERNAME);
+            assert!(session.is_challenge_expired(), "{} was deleted", deleted);
+            assert!(!session.invited.contains(&Player::DELETED_USERNAME.to_string()));
+        }
+    }
+
+    #[test]
+    fn renamed_opponent_carries_the_challenge() {
+        let mut session = challenge();
+        session.rename_player("bob", "carol");
+        assert!(!session.is_challenge_expired());
+        assert_eq!(session.challenge.as_ref().unwrap().opponent, "carol");
+        assert!(session.is_invited(&Player::new("carol".to_string(), String::new())));
+        assert!(!session.is_invited(&Player::new("bob".to_string(), String::new())));
+    }
+}
@@ -0,0 +1,86 @@
+use serde::Serialize;
+use crate::ai::Difficulty;
+use crate::game::{Game, MoveOutcome};
+use crate::model::session::{Challenge, Move, Session};
+
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum SessionStatus {
+    WaitingForOpponent,
+    InProgress,
+    Won,
+    Draw,
+}
+
+impl SessionStatus {
+    pub fn new(session: &Session<impl Game + Clone>) -> Self {
+        match session.status {
+            MoveOutcome::Win(_) => SessionStatus::Won,
+            MoveOutcome::Draw => SessionStatus::Draw,
+            MoveOutcome::Continue if session.can_join() => SessionStatus::WaitingForOpponent,
+            MoveOutcome::Continue => SessionStatus::InProgress,
+        }
+    }
+}
+
+//*
+// A session as the client sees it, returned by every session route so clients don't have to parse
+// Game::print. Players are referred to by their index in players
+// */
+#[derive(Serialize)]
+pub struct SessionView {
+    pub session_id: String,
+    //Short code to give to the opponent, None once the session is saved and for everyone but the
+    //creator of a private session
+    pub join_code: Option<String>,
+    pub private: bool,
+    //Set until the challenged player accepts
+    pub challenge: Option<Challenge>,
+    pub game: String,
+    //Usernames, the second one is None until someone joins
+    pub players: [Option<String>; 2],
+    //Difficulty of the computer sitting in players[1]
+    pub bot: Option<Difficulty>,
+    pub board: Vec<Vec<String>>,
+    //Ultimate XO: winner of each small board, "-" for a draw, and the small board to play in next
+    pub meta_board: Option<Vec<Vec<String>>>,
+    pub next_board: Option<usize>,
+    //Player to move, None when nobody can move
+    pub turn: Option<usize>,
+    pub status: SessionStatus,
+    pub winner: Option<usize>,
+    pub moves: Vec<Move>,
+    //Player waiting for the opponent to accept a takeback
+    pub takeback_request: Option<usize>,
+}
+
+impl SessionView {
+    pub fn new(session: &Session<impl Game + Clone>) -> Self {
+        let status = SessionStatus::new(session);
+        SessionView {
+            session_id: session.get_session_id().0,
+            join_code: if session.private { None } else { session.join_code().map(str::to_string) },
+            private: session.private,
+            challenge: session.challenge.clone(),
+            game: session.game.name(),
+            players: session.players.clone().map(|player| player.map(|player| player.get_username())),
+            bot: session.bot.as_ref().map(|bot| bot.difficulty()),
+            board: session.game.board(),
+            meta_board: session.game.meta_board(),
+            next_board: session.game.next_board(),
+            turn: if status == SessionStatus::InProgress { Some(session.turn) } else { None },
+            status,
+            winner: session.status.winner(),
+            moves: session.moves.clone(),
+            takeback_request: session.takeback_request,
+        }
+    }
+
+    //The join code of a private session is the invite, only its creator gets to see it
+    pub fn for_creator(session: &Session<impl Game + Clone>) -> Self {
+        SessionView {
+            join_code: session.join_code().map(str::to_string),
+            ..Self::new(session)
+        }
+    }
+}